@@ -3,59 +3,283 @@ use crate::Error;
 pub struct AcmeOrder<'a> {
     config: &'a crate::Config,
     csr: crate::X509Csr,
+    /// Private key of generated CSR
+    private_key: Option<crate::PrivateKey>,
+    /// Certificate to be replaced by this order (RFC 9773)
+    replaces: Option<instant_acme::CertificateIdentifier<'static>>,
+    /// _acme-challenge.<host> -> record name where TXT is published
+    challenge_records: std::collections::HashMap<String, String>,
+    /// record name -> zone restriction in config
+    zone_pins: std::collections::HashMap<String, crate::DnsZonePin>,
 }
 
 pub struct AcmeOrderBuilder<'a> {
     config: &'a crate::Config,
     cert_cfg: &'a crate::CertReqConfig,
+    private_key: Option<crate::PrivateKey>,
+    replaces: Option<instant_acme::CertificateIdentifier<'static>>,
 }
 
 impl<'a> AcmeOrder<'a> {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new<'b: 'a>(
         config: &'b crate::Config,
         cert_cfg: &'b crate::CertReqConfig,
     ) -> Result<AcmeOrderBuilder<'a>, Error> {
-        Ok(AcmeOrderBuilder { config, cert_cfg })
+        Ok(AcmeOrderBuilder {
+            config,
+            cert_cfg,
+            private_key: None,
+            replaces: None,
+        })
     }
 }
 
 impl<'a> AcmeOrderBuilder<'a> {
-    pub fn load_and_check_csr(
+    /// Generate CSR from domains in config, signed by this key
+    pub fn private_key(mut self, private_key: crate::PrivateKey) -> Self {
+        self.private_key = Some(private_key);
+        self
+    }
+
+    /// Check if the current certificate should be replaced by new one.
+    /// ACME Renewal Information (RFC 9773) is preferred if the ACME server supports it,
+    /// otherwise renew_before_days decides.
+    /// Called before CSR and DNS work, so that skipping costs nothing.
+    pub async fn needs_renewal(
         &self,
+        current: &crate::X509Crt,
+        renew_before_days: u32,
+    ) -> Result<bool, Error> {
+        use crate::cname::normalize;
+
+        // Hostnames in CSR or domains have been changed, compared case-insensitively
+        let requested = if let Some(csr_file_name) = self.cert_cfg.csr_file_name() {
+            let csr = crate::csr::X509Csr::from_pem_file(csr_file_name)?;
+            csr.subjects()
+                .map(normalize)
+                .collect::<std::collections::BTreeSet<_>>()
+        } else {
+            self.cert_cfg
+                .domains()
+                .iter()
+                .map(|domain| normalize(domain))
+                .collect()
+        };
+        let current_subjects = current
+            .subjects()
+            .map(normalize)
+            .collect::<std::collections::BTreeSet<_>>();
+        if requested != current_subjects {
+            log::info!("Hostnames differ from the current certificate");
+            return Ok(true);
+        }
+
+        if let Some(renewal_time) = self.renewal_time(current).await {
+            let now = unix_time_now();
+            log::info!("ARI renewal time is {} seconds later", renewal_time - now);
+            return Ok(renewal_time <= now);
+        }
+
+        let days_to_expiration = current.days_to_expiration();
+        log::info!("Certificate expires in {} days", days_to_expiration);
+        Ok(days_to_expiration < renew_before_days as i64)
+    }
+
+    /// Pick a renewal time (UNIX timestamp) in the window suggested by the ACME server
+    async fn renewal_time(&self, current: &crate::X509Crt) -> Option<i64> {
+        let cert_id = current.certificate_identifier()?;
+        let (renewal_info, _retry_after) = self
+            .config
+            .account()
+            .renewal_info(&cert_id)
+            .await
+            .inspect_err(|e| log::info!("ACME renewal information is not available: {}", e))
+            .ok()?;
+
+        let window = renewal_info.suggested_window;
+        if let Some(explanation_url) = renewal_info.explanation_url {
+            log::info!("ARI explanation: {}", explanation_url);
+        }
+
+        // Uniform random time in the window, RFC 9773 section 4.2
+        let start = window.start.unix_timestamp();
+        let end = window.end.unix_timestamp();
+        let window_secs = (end - start).max(0) as u64;
+        Some(start + fastrand::u64(0..=window_secs) as i64)
+    }
+
+    /// New certificate replaces the current one, informed to ACME server
+    pub fn replaces(mut self, current: &crate::X509Crt) -> Self {
+        self.replaces = current.certificate_identifier();
+        self
+    }
+
+    pub async fn load_and_check_csr(
+        self,
         dns_zones: &crate::AllDnsZones,
     ) -> Result<AcmeOrder<'a>, Error> {
-        // Read CSR file
-        let csr = crate::csr::X509Csr::from_pem_file(self.cert_cfg.csr_file_name())?;
+        let csr = if let Some(csr_file_name) = self.cert_cfg.csr_file_name() {
+            // Read CSR file
+            crate::csr::X509Csr::from_pem_file(csr_file_name)?
+        } else if let Some(private_key) = &self.private_key {
+            // Generate CSR
+            crate::csr::X509Csr::generate(self.cert_cfg.domains(), private_key)?
+        } else {
+            return Err(Error::InvalidCertRequest(format!(
+                "{}: no private key for domains",
+                self.cert_cfg.crt_file_name()
+            )));
+        };
 
+        // Built on first use, the system resolver config may not be available
+        let mut cname_resolver = None::<crate::dns::CnameResolver>;
+
+        let mut challenge_records = std::collections::HashMap::new();
+        let mut zone_pins = std::collections::HashMap::new();
+        // Pin of each challenge record with the first hostname, example.com and *.example.com share one
+        let mut pinned_by = std::collections::HashMap::<String, (&str, crate::DnsZonePin)>::new();
         for hostname in csr.subjects() {
-            let challenge_record = format!("_acme-challenge.{}", hostname);
-            let canonical_challenge_record = self.config.canonical_host(&challenge_record);
+            // *.example.com is validated by _acme-challenge.example.com
+            let challenge_record = format!("_acme-challenge.{}", hostname.trim_start_matches("*."));
+            let cname = self.config.cname_of(&challenge_record);
+            let needs_resolver = match cname {
+                Some(_) => self.config.verify_cname(),
+                None => self.config.discover_cname(),
+            };
+            if needs_resolver && cname_resolver.is_none() {
+                cname_resolver = Some(crate::dns::CnameResolver::new(self.config.dns_check())?);
+            }
+            let canonical_challenge_record = match (cname, &cname_resolver) {
+                (Some(cname), Some(cname_resolver)) if self.config.verify_cname() => {
+                    // Fail before ordering if the delegation is not published
+                    let published = cname_resolver.resolve(&challenge_record).await?;
+                    let expected = cname_resolver.resolve(&cname).await?;
+                    if !published.eq_ignore_ascii_case(&expected) {
+                        return Err(Error::CnameMismatch(format!(
+                            "{} resolves to {}, expected {}",
+                            challenge_record, published, cname
+                        )));
+                    }
+                    cname
+                }
+                (Some(cname), _) => cname,
+                (None, Some(cname_resolver)) if self.config.discover_cname() => {
+                    cname_resolver.resolve(&challenge_record).await?
+                }
+                (None, _) => challenge_record.clone(),
+            };
             log::debug!(
                 "ACME DNS01 challenge record will be {} for host {}",
                 canonical_challenge_record,
                 hostname
             );
 
-            let zone = dns_zones.find_zone(canonical_challenge_record);
+            let zone_pin = self.cert_cfg.zone_pin(hostname);
+            match pinned_by.get(&canonical_challenge_record) {
+                Some((other_hostname, other_pin)) if *other_pin != zone_pin => {
+                    return Err(Error::InvalidCertRequest(format!(
+                        "{}: {} and {} share {} but are pinned to different zones",
+                        self.cert_cfg.crt_file_name(),
+                        other_hostname,
+                        hostname,
+                        canonical_challenge_record
+                    )));
+                }
+                Some(_) => {}
+                None => {
+                    pinned_by.insert(
+                        canonical_challenge_record.clone(),
+                        (hostname, zone_pin.clone()),
+                    );
+                }
+            }
+            dns_zones
+                .load_delegations(&canonical_challenge_record)
+                .await;
+            let zone = dns_zones.find_pinned_zone(&canonical_challenge_record, &zone_pin);
             if let Some(_zone) = zone {
                 // ok
             } else {
-                return Err(Error::NoDnsZone(canonical_challenge_record.to_string()));
+                return Err(Error::NoDnsZone(canonical_challenge_record));
+            }
+            if zone_pin != crate::DnsZonePin::default() {
+                zone_pins.insert(canonical_challenge_record.clone(), zone_pin);
             }
+            challenge_records.insert(challenge_record, canonical_challenge_record);
         }
 
         Ok(AcmeOrder {
             config: self.config,
             csr,
+            private_key: self.private_key,
+            replaces: self.replaces,
+            challenge_records,
+            zone_pins,
         })
     }
 }
 
 impl AcmeOrder<'_> {
+    /// PEM encoded private key, if CSR was generated
+    pub fn private_key_pem(&self) -> Option<String> {
+        self.private_key.as_ref().map(|key| key.to_pem())
+    }
+
+    /// Zone restriction of the challenge record in config
+    fn zone_pin(&self, record_name: &str) -> &crate::DnsZonePin {
+        const NO_PIN: &crate::DnsZonePin = &crate::DnsZonePin {
+            hosted_zone_id: None,
+            dns_provider: None,
+        };
+        self.zone_pins.get(record_name).unwrap_or(NO_PIN)
+    }
+
     pub async fn request_certificate(
         &self,
         dns_zones: &crate::AllDnsZones,
     ) -> Result<AcmeIssuedCertificate, Error> {
+        // (record name, TXT values) registered for DNS01 challenges
+        let mut challenge_records = Vec::<(String, Vec<String>)>::new();
+
+        let result = self
+            .order_certificate(dns_zones, &mut challenge_records)
+            .await;
+
+        // Remove challenge records whether the order succeeded or not
+        if self.config.keep_challenge_records() {
+            log::info!("Keep {} challenge TXT records", challenge_records.len());
+        } else {
+            // Concurrently, deletes in the same zone may be batched by the provider
+            let results = futures::future::join_all(challenge_records.iter().map(
+                |(record_name, txt_values)| {
+                    dns_zones.delete_pinned_txt_record(
+                        record_name,
+                        txt_values,
+                        self.zone_pin(record_name),
+                    )
+                },
+            ))
+            .await;
+            for ((record_name, _), result) in challenge_records.iter().zip(results) {
+                if let Err(e) = result {
+                    // Cleanup failure does not affect issued certificate
+                    log::warn!("Failed to delete TXT record {}: {:?}", record_name, e);
+                }
+            }
+        }
+
+        result
+    }
+
+    async fn order_certificate(
+        &self,
+        dns_zones: &crate::AllDnsZones,
+        challenge_records: &mut Vec<(String, Vec<String>)>,
+    ) -> Result<AcmeIssuedCertificate, Error> {
+        use instant_acme::AuthorizationStatus::Pending;
+        use instant_acme::ChallengeType::Dns01;
+
         let validate_hostnames = self
             .csr
             .subjects()
@@ -63,61 +287,159 @@ impl AcmeOrder<'_> {
             .map(|hostname| instant_acme::Identifier::Dns(hostname.to_string()))
             .collect::<Vec<_>>();
 
-        let mut order = self
-            .config
-            .account()
-            .new_order(&instant_acme::NewOrder::new(&validate_hostnames))
-            .await?;
+        let account = self.config.account();
+        let new_order = || instant_acme::NewOrder::new(&validate_hostnames);
+        let mut order = if let Some(replaces) = &self.replaces {
+            match account
+                .new_order(&new_order().replaces(replaces.clone()))
+                .await
+            {
+                Ok(order) => order,
+                Err(instant_acme::Error::Api(problem)) if is_replaces_rejected(&problem) => {
+                    // e.g. the certificate was already replaced in previous run
+                    log::warn!("Order with replaces {} failed: {}", replaces, problem);
+                    account.new_order(&new_order()).await?
+                }
+                Err(e) => return Err(e.into()),
+            }
+        } else {
+            account.new_order(&new_order()).await?
+        };
 
+        // Collect ACME challenges need to be verified.
+        // example.com and *.example.com share the same record with different values.
+        let mut txt_values_by_record = std::collections::BTreeMap::<String, Vec<String>>::new();
+        // hostname, "*." prefixed if wildcard -> (challenge record name, TXT value)
+        let mut challenge_by_host = std::collections::HashMap::<String, (String, String)>::new();
         let mut authorizations = order.authorizations();
-
-        // Collect ACME challenges need to be verified, and register DNS records
         while let Some(Ok(mut auth)) = authorizations.next().await {
-            use instant_acme::AuthorizationStatus::Pending;
-            use instant_acme::ChallengeType::Dns01;
-            match auth.status {
-                Pending => {
-                    // challenge & authorize
+            // Valid, Invalid, Revoked, Expired authorizations need no challenge
+            if auth.status == Pending {
+                // challenge & authorize
+                if let Some(dns_challenge) = auth.challenge(Dns01) {
+                    // DNS01 challenge
+                    if let instant_acme::Identifier::Dns(hostname) =
+                        dns_challenge.identifier().identifier
+                    {
+                        // Retrive TXT record value
+                        let key_auth = dns_challenge.key_authorization();
+
+                        let challenge_record = format!("_acme-challenge.{}", hostname);
+                        let canonical_challenge_record = self
+                            .challenge_records
+                            .get(&challenge_record)
+                            .cloned()
+                            .unwrap_or_else(|| self.config.canonical_host(&challenge_record));
+
+                        log::debug!(
+                            "DNS01 challenge record: {} -> {}",
+                            challenge_record,
+                            canonical_challenge_record
+                        );
+
+                        txt_values_by_record
+                            .entry(canonical_challenge_record.clone())
+                            .or_default()
+                            .push(key_auth.dns_value());
+                        challenge_by_host.insert(
+                            dns_challenge.identifier().to_string(),
+                            (canonical_challenge_record, key_auth.dns_value()),
+                        );
+                    } else {
+                        return Err(Error::InvalidAcmeOrder);
+                    }
+                } else {
+                    // Oops, No DNS challenge supported in the ACME server?
+                    return Err(Error::DnsChallengeNotSupported);
+                };
+            }
+        }
+
+        // Register all DNS records first, all values of the same record at once
+        let records = txt_values_by_record.into_iter().collect::<Vec<_>>();
+        let update_results =
+            futures::future::join_all(records.iter().map(|(record_name, txt_values)| {
+                dns_zones.update_pinned_txt_record(
+                    record_name,
+                    txt_values,
+                    self.zone_pin(record_name),
+                )
+            }))
+            .await;
+
+        let mut record_errors = std::collections::HashMap::<&str, Error>::new();
+        let mut dns_changes = Vec::new();
+        for ((record_name, txt_values), result) in records.iter().zip(update_results) {
+            match result {
+                Ok(dns_change) => {
+                    challenge_records.push((record_name.clone(), txt_values.clone()));
+                    dns_changes.push((record_name.as_str(), dns_change));
+                }
+                Err(e) => {
+                    record_errors.insert(record_name.as_str(), e);
+                }
+            }
+        }
+
+        // Then wait for propergation of all records concurrently
+        let wait_results = futures::future::join_all(
+            dns_changes
+                .iter()
+                .map(|(_record_name, dns_change)| dns_change.wait_for_propergation(90)),
+        )
+        .await;
+        for ((record_name, _dns_change), result) in dns_changes.iter().zip(wait_results) {
+            if let Err(e) = result {
+                record_errors.insert(record_name, e);
+            }
+        }
+
+        // Report errors for each authorization
+        let mut failures = challenge_by_host
+            .iter()
+            .filter_map(|(hostname, (record_name, txt_value))| {
+                record_errors.get(record_name.as_str()).map(|e| {
+                    log::error!("DNS01 challenge for {} failed: {}", hostname, e);
+                    AuthorizationFailure {
+                        hostname: hostname.clone(),
+                        problem_type: None,
+                        detail: e.to_string(),
+                        txt_value: Some(txt_value.clone()),
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        if failures.is_empty() {
+            // DNS changes have been propergated. Let ACME server to validate them.
+            let mut authorizations = order.authorizations();
+            while let Some(Ok(mut auth)) = authorizations.next().await {
+                if auth.status == Pending {
                     if let Some(mut dns_challenge) = auth.challenge(Dns01) {
-                        // DNS01 challenge
-                        if let instant_acme::Identifier::Dns(hostname) =
-                            dns_challenge.identifier().identifier
-                        {
-                            // Retrive TXT record value
-                            let key_auth = dns_challenge.key_authorization();
-
-                            let challenge_record = format!("_acme-challenge.{}", hostname);
-                            let canonical_challenge_record =
-                                self.config.canonical_host(&challenge_record);
-
-                            log::debug!(
-                                "DNS01 challenge record: {} -> {}",
-                                challenge_record,
-                                canonical_challenge_record
-                            );
-
-                            dns_zones
-                                .update_txt_record(
-                                    canonical_challenge_record,
-                                    &key_auth.dns_value(),
-                                )
-                                .await?
-                                .wait_for_propergation(90)
-                                .await?;
-
-                            // DNS change has been propergated. Let ACME server to validate them.
-                            dns_challenge.set_ready().await?;
-                        } else {
+                        if !matches!(
+                            dns_challenge.identifier().identifier,
+                            instant_acme::Identifier::Dns(_)
+                        ) {
                             return Err(Error::InvalidAcmeOrder);
                         }
-                    } else {
-                        // Oops, No DNS challenge supported in the ACME server?
-                        return Err(Error::DnsChallengeNotSupported);
-                    };
+                        let hostname = dns_challenge.identifier().to_string();
+                        if let Err(e) = dns_challenge.set_ready().await {
+                            log::error!("DNS01 challenge for {} failed: {}", hostname, e);
+                            let txt_value = challenge_by_host.get(&hostname);
+                            failures.push(AuthorizationFailure::from_acme_error(
+                                hostname,
+                                e,
+                                txt_value.map(|(_, txt_value)| txt_value.clone()),
+                            ));
+                        }
+                    }
                 }
-                // Valid, Invalid, Revoked, Expired
-                _ => {}
-            };
+            }
+        }
+
+        if !failures.is_empty() {
+            failures.sort_by(|a, b| a.hostname.cmp(&b.hostname));
+            return Err(Error::AuthorizationFailed(failures));
         }
         log::info!("All hostnames are validated");
 
@@ -133,6 +455,40 @@ impl AcmeOrder<'_> {
             instant_acme::OrderStatus::Ready | instant_acme::OrderStatus::Valid => {
                 // Ready for issueing certificate
             }
+            instant_acme::OrderStatus::Invalid => {
+                // ACME server could not validate, tell why for each hostname
+                let mut failures = Vec::new();
+                let mut authorizations = order.authorizations();
+                while let Some(Ok(mut auth)) = authorizations.next().await {
+                    if auth.status != instant_acme::AuthorizationStatus::Invalid {
+                        continue;
+                    }
+                    if let Some(dns_challenge) = auth.challenge(Dns01) {
+                        let hostname = dns_challenge.identifier().to_string();
+                        let txt_value = challenge_by_host
+                            .get(&hostname)
+                            .map(|(_, txt_value)| txt_value.clone());
+                        let failure = match dns_challenge.error.clone() {
+                            Some(problem) => {
+                                AuthorizationFailure::from_problem(hostname, problem, txt_value)
+                            }
+                            None => AuthorizationFailure {
+                                hostname,
+                                problem_type: None,
+                                detail: format!("challenge is {:?}", dns_challenge.status),
+                                txt_value,
+                            },
+                        };
+                        log::error!("DNS01 challenge failed for {}", failure);
+                        failures.push(failure);
+                    }
+                }
+                if failures.is_empty() {
+                    return Err(Error::AcmeChallengeIncomplete);
+                }
+                failures.sort_by(|a, b| a.hostname.cmp(&b.hostname));
+                return Err(Error::AuthorizationFailed(failures));
+            }
             _ => {
                 // Unexpected invalid status
                 return Err(Error::AcmeChallengeIncomplete);
@@ -157,11 +513,88 @@ impl AcmeOrder<'_> {
     }
 }
 
+/// Why DNS01 challenge of a hostname failed
+#[derive(Debug)]
+pub struct AuthorizationFailure {
+    /// Hostname of the authorization, "*." prefixed if wildcard
+    pub hostname: String,
+    /// ACME problem type, e.g. "urn:ietf:params:acme:error:unauthorized".
+    /// None if the DNS record could not be published.
+    pub problem_type: Option<String>,
+    /// Problem detail from the ACME server, e.g. which TXT value was seen,
+    /// or the error of DNS change
+    pub detail: String,
+    /// TXT value published for the challenge
+    pub txt_value: Option<String>,
+}
+
+impl AuthorizationFailure {
+    fn from_problem(
+        hostname: String,
+        problem: instant_acme::Problem,
+        txt_value: Option<String>,
+    ) -> Self {
+        Self {
+            hostname,
+            problem_type: problem.r#type,
+            detail: problem.detail.unwrap_or_default(),
+            txt_value,
+        }
+    }
+
+    fn from_acme_error(
+        hostname: String,
+        e: instant_acme::Error,
+        txt_value: Option<String>,
+    ) -> Self {
+        match e {
+            instant_acme::Error::Api(problem) => Self::from_problem(hostname, problem, txt_value),
+            e => Self {
+                hostname,
+                problem_type: None,
+                detail: e.to_string(),
+                txt_value,
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for AuthorizationFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.hostname, self.detail)?;
+        if let Some(problem_type) = &self.problem_type {
+            // Short form, e.g. "unauthorized"
+            let short = problem_type
+                .strip_prefix("urn:ietf:params:acme:error:")
+                .unwrap_or(problem_type);
+            write!(f, " ({})", short)?;
+        }
+        if let Some(txt_value) = &self.txt_value {
+            write!(f, ", expected TXT \"{}\"", txt_value)?;
+        }
+        Ok(())
+    }
+}
+
+/// "host1: problem; host2: problem" for Error::AuthorizationFailed
+pub(crate) fn join_failures(failures: &[AuthorizationFailure]) -> String {
+    failures
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
 pub struct AcmeIssuedCertificate {
     crt_pem: Vec<x509_parser::pem::Pem>,
 }
 
 impl AcmeIssuedCertificate {
+    /// Parsed server certificate
+    pub fn server_certificate(&self) -> Result<crate::X509Crt, Error> {
+        crate::X509Crt::try_from(self.crt_pem[0].contents.clone())
+    }
+
     pub fn server_certificate_pem(&self) -> String {
         Self::to_pem_string(&self.crt_pem[0])
     }
@@ -187,3 +620,22 @@ impl AcmeIssuedCertificate {
         )
     }
 }
+
+pub(crate) fn unix_time_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// ACME server refused the replaces field of newOrder, RFC 9773 section 5
+fn is_replaces_rejected(problem: &instant_acme::Problem) -> bool {
+    match problem.r#type.as_deref() {
+        Some("urn:ietf:params:acme:error:alreadyReplaced") => true,
+        Some("urn:ietf:params:acme:error:malformed") => problem
+            .detail
+            .as_deref()
+            .is_some_and(|detail| detail.contains("replaces")),
+        _ => false,
+    }
+}
@@ -13,7 +13,7 @@ async fn lambda_handler(
     use acme_client_route53::*;
 
     let config = Config::from_file("acme.toml").await?;
-    issue_certificates(&config).await?;
+    let report = issue_certificates(&config).await?;
 
-    Ok(serde_json::json!({}))
+    Ok(serde_json::to_value(&report)?)
 }
@@ -41,6 +41,66 @@ enum CliCommands {
     },
     /// Update certificates
     Update {},
+    /// Revoke a certificate
+    Revoke {
+        /// Certificate file name or S3 URL (s3://bucket/key)
+        #[arg(long, value_name = "PATH")]
+        cert: String,
+
+        /// Revocation reason
+        #[arg(long, value_enum)]
+        reason: Option<RevokeReason>,
+    },
+    /// Register an account on acme-dns server for a host
+    AcmeDnsRegister {
+        /// acme-dns API endpoint
+        #[arg(long, value_name = "URL")]
+        api_url: String,
+
+        /// Networks allowed to update, e.g. 192.0.2.0/24
+        #[arg(long, value_name = "CIDR")]
+        allow_from: Vec<String>,
+
+        /// Hostname of certificate, delegating _acme-challenge to acme-dns
+        hostname: String,
+    },
+}
+
+/// CRLReason defined in RFC5280 section 5.3.1.
+/// certificateHold and removeFromCRL are left out, ACME servers refuse them.
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum RevokeReason {
+    #[value(name = "unspecified")]
+    Unspecified,
+    #[value(name = "keyCompromise")]
+    KeyCompromise,
+    #[value(name = "cACompromise")]
+    CaCompromise,
+    #[value(name = "affiliationChanged")]
+    AffiliationChanged,
+    #[value(name = "superseded")]
+    Superseded,
+    #[value(name = "cessationOfOperation")]
+    CessationOfOperation,
+    #[value(name = "privilegeWithdrawn")]
+    PrivilegeWithdrawn,
+    #[value(name = "aACompromise")]
+    AaCompromise,
+}
+
+impl From<RevokeReason> for instant_acme::RevocationReason {
+    fn from(reason: RevokeReason) -> Self {
+        match reason {
+            RevokeReason::Unspecified => Self::Unspecified,
+            RevokeReason::KeyCompromise => Self::KeyCompromise,
+            RevokeReason::CaCompromise => Self::CaCompromise,
+            RevokeReason::AffiliationChanged => Self::AffiliationChanged,
+            RevokeReason::Superseded => Self::Superseded,
+            RevokeReason::CessationOfOperation => Self::CessationOfOperation,
+            RevokeReason::PrivilegeWithdrawn => Self::PrivilegeWithdrawn,
+            RevokeReason::AaCompromise => Self::AaCompromise,
+        }
+    }
 }
 
 /// main() for generic environment
@@ -73,7 +133,85 @@ async fn main() {
         }
         CliCommands::Update {} => {
             let config = Config::from_file(&cli.config_file).await.unwrap();
-            issue_certificates(&config).await.unwrap();
+            let report = issue_certificates(&config).await.unwrap();
+            println!("{}", report);
+            if report.has_failure() {
+                std::process::exit(1);
+            }
+        }
+        CliCommands::Revoke { cert, reason } => {
+            let config = Config::from_file(&cli.config_file).await.unwrap();
+            let crt = revoke_certificate(&config, &cert, reason.map(|r| r.into()))
+                .await
+                .unwrap();
+            println!("Revoked {}, serial {}", cert, crt.serial_hex());
+        }
+        CliCommands::AcmeDnsRegister {
+            api_url,
+            allow_from,
+            hostname,
+        } => {
+            let account = AcmeDnsProvider::register(&api_url, &allow_from)
+                .await
+                .unwrap();
+            println!("Create CNAME record in your DNS:");
+            println!(
+                "  _acme-challenge.{}. CNAME {}.",
+                hostname, account.fulldomain
+            );
+            println!();
+            println!("Then add to {}:", cli.config_file.display());
+            println!("  [[acme_dns]]");
+            println!("  api_url = {:?}", api_url);
+            println!("  [acme_dns.accounts.{:?}]", hostname);
+            println!("  username = {:?}", account.username);
+            println!("  password = {:?}", account.password);
+            println!("  subdomain = {:?}", account.subdomain);
+            println!("  fulldomain = {:?}", account.fulldomain);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn revoke_reason_test() {
+        use clap::Parser;
+
+        // CLI name -> CRLReason code
+        let expected = [
+            ("unspecified", 0),
+            ("keyCompromise", 1),
+            ("cACompromise", 2),
+            ("affiliationChanged", 3),
+            ("superseded", 4),
+            ("cessationOfOperation", 5),
+            ("privilegeWithdrawn", 9),
+            ("aACompromise", 10),
+        ];
+        for (name, code) in expected {
+            let cli =
+                Cli::try_parse_from(["acme", "revoke", "--cert", "crt.pem", "--reason", name])
+                    .unwrap();
+            let CliCommands::Revoke {
+                reason: Some(reason),
+                ..
+            } = cli.command
+            else {
+                panic!("{} is not parsed as revoke reason", name);
+            };
+            assert_eq!(code, instant_acme::RevocationReason::from(reason) as u8);
         }
+        assert!(Cli::try_parse_from([
+            "acme",
+            "revoke",
+            "--cert",
+            "crt.pem",
+            "--reason",
+            "certificateHold"
+        ])
+        .is_err());
     }
 }
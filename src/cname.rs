@@ -0,0 +1,120 @@
+//! `[cname]` delegation table of challenge records
+use crate::Error;
+use std::collections::HashMap;
+
+/// Exact names and wildcard patterns, e.g.
+/// `"_acme-challenge.*.customers.example.com" = "{1}.acme.example.net"`
+#[derive(Clone, Debug, Default)]
+pub struct CnameMap {
+    exact: HashMap<String, String>,
+    patterns: Vec<CnamePattern>,
+}
+
+/// Each `*` matches one label, `{n}` in target is replaced by the n-th match
+#[derive(Clone, Debug)]
+struct CnamePattern {
+    name: String,
+    /// Labels of the pattern, None for `*`
+    labels: Vec<Option<String>>,
+    target: String,
+}
+
+impl TryFrom<HashMap<String, String>> for CnameMap {
+    type Error = Error;
+
+    fn try_from(entries: HashMap<String, String>) -> Result<Self, Error> {
+        let mut exact = HashMap::new();
+        let mut patterns = Vec::new();
+        for (name, target) in entries {
+            let name = normalize(&name);
+            let labels = name
+                .split('.')
+                .map(|label| (label != "*").then(|| label.to_string()))
+                .collect::<Vec<_>>();
+
+            // {n} must refer to one of the wildcards
+            let wildcards = labels.iter().filter(|label| label.is_none()).count();
+            if let Some(idx) = placeholders(&target).find(|idx| !(1..=wildcards).contains(idx)) {
+                return Err(Error::InvalidCname(format!(
+                    "{} = {}: no wildcard for {{{}}}",
+                    name, target, idx
+                )));
+            }
+
+            if wildcards == 0 {
+                exact.insert(name, target);
+            } else {
+                patterns.push(CnamePattern {
+                    name,
+                    labels,
+                    target,
+                });
+            }
+        }
+        // More specific pattern first: fewer wildcards, more labels,
+        // then wildcards in deeper labels. The name breaks remaining ties.
+        patterns.sort_by_cached_key(|pattern| {
+            let wildcards = pattern.labels.iter().filter(|l| l.is_none()).count();
+            let leftmost_wildcard = pattern.labels.iter().position(|l| l.is_none());
+            (
+                wildcards,
+                std::cmp::Reverse(pattern.labels.len()),
+                leftmost_wildcard,
+                pattern.name.clone(),
+            )
+        });
+
+        Ok(Self { exact, patterns })
+    }
+}
+
+impl CnameMap {
+    /// Delegated name of the challenge record, exact entries take precedence
+    pub fn get(&self, name: &str) -> Option<String> {
+        let name = normalize(name);
+        if let Some(target) = self.exact.get(&name) {
+            return Some(target.clone());
+        }
+        self.patterns
+            .iter()
+            .find_map(|pattern| pattern.apply(&name))
+    }
+}
+
+impl CnamePattern {
+    fn apply(&self, name: &str) -> Option<String> {
+        let labels = name.split('.').collect::<Vec<_>>();
+        if labels.len() != self.labels.len() {
+            return None;
+        }
+
+        let mut captures = Vec::new();
+        for (label, pattern) in labels.iter().zip(&self.labels) {
+            match pattern {
+                Some(pattern) if pattern == label => {}
+                Some(_) => return None,
+                None => captures.push(*label),
+            }
+        }
+
+        // Replace larger index first, {1} must not break {10}
+        let mut target = self.target.clone();
+        for (idx, capture) in captures.iter().enumerate().rev() {
+            target = target.replace(&format!("{{{}}}", idx + 1), capture);
+        }
+        Some(target)
+    }
+}
+
+/// Indices of `{n}` placeholders in the target
+fn placeholders(target: &str) -> impl Iterator<Item = usize> + '_ {
+    target
+        .split('{')
+        .skip(1)
+        .filter_map(|rest| rest.split_once('}'))
+        .filter_map(|(idx, _)| idx.parse::<usize>().ok())
+}
+
+pub(crate) fn normalize(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
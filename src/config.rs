@@ -4,18 +4,48 @@ use crate::Error;
 
 pub struct Config {
     account: instant_acme::Account,
-    cname: std::collections::HashMap<String, String>,
+    cname: crate::CnameMap,
+    keep_challenge_records: bool,
+    discover_cname: bool,
+    verify_cname: bool,
+    rfc2136: Vec<crate::Rfc2136Config>,
+    acme_dns: Vec<crate::AcmeDnsConfig>,
+    dns_responder: Option<crate::DnsResponderConfig>,
+    exec: Vec<crate::ExecConfig>,
+    powerdns: Vec<crate::PowerDnsConfig>,
+    cloudflare: Vec<crate::CloudflareConfig>,
+    dns_check: crate::DnsCheckConfig,
     cert_requests: Vec<CertReqConfig>,
 }
 
 #[derive(Clone, serde::Deserialize)]
 pub struct CertReqConfig {
     /// Certificate signing request filename (as input file)
-    csr_file: String,
+    csr_file: Option<String>,
+    /// Hostnames of generated CSR, instead of csr_file
+    #[serde(default)]
+    domains: Vec<String>,
+    /// Private key algorithm of generated CSR
+    #[serde(default)]
+    key_type: crate::KeyType,
+    /// Private key filename of generated CSR (as output file)
+    out_key_file: Option<String>,
+    /// Reuse the private key in out_key_file, instead of generating new one every time
+    #[serde(default)]
+    reuse_key: bool,
     /// Issued server certificate filename (as output file)
     out_crt_file: String,
     /// Issuer (CA) intermidiate certificate filename (as output file)
     out_iss_file: Option<String>,
+    /// Renew only when the current certificate expires within this days
+    renew_before_days: Option<u32>,
+    /// Zone of challenge records, e.g. Route53 hosted zone id
+    hosted_zone_id: Option<String>,
+    /// Provider of challenge records, e.g. "Route53", "Lightsail"
+    dns_provider: Option<String>,
+    /// hosted_zone_id and dns_provider by hostname
+    #[serde(default)]
+    hostnames: std::collections::HashMap<String, crate::DnsZonePin>,
     #[serde(flatten)]
     extra: std::collections::HashMap<String, toml::Value>,
 }
@@ -30,11 +60,49 @@ struct NewConfigToml {
 struct ConfigToml {
     #[serde(rename = "account")]
     credential: instant_acme::AccountCredentials,
+    /// Delegation of challenge records, exact names or patterns with `*`
     #[serde(default)]
     cname: std::collections::HashMap<String, String>,
+    /// Leave _acme-challenge TXT records after validation, for debugging
+    #[serde(default)]
+    keep_challenge_records: bool,
+    /// Follow CNAME of _acme-challenge records on DNS, cname table overrides it
+    #[serde(default)]
+    discover_cname: bool,
+    /// Check that CNAME in cname table is published before ordering, on by default.
+    /// Set false when the resolvers see another view, e.g. split-horizon or VPC DNS
+    #[serde(default = "ConfigToml::default_verify_cname")]
+    verify_cname: bool,
+    /// DNS servers accepting RFC 2136 dynamic update
+    #[serde(default)]
+    rfc2136: Vec<crate::Rfc2136Config>,
+    /// acme-dns servers, _acme-challenge of the hosts are delegated to
+    #[serde(default)]
+    acme_dns: Vec<crate::AcmeDnsConfig>,
+    /// Serve the delegated challenge zone by this process
+    dns_responder: Option<crate::DnsResponderConfig>,
+    /// External commands for other DNS services
+    #[serde(default)]
+    exec: Vec<crate::ExecConfig>,
+    /// PowerDNS Authoritative servers
+    #[serde(default)]
+    powerdns: Vec<crate::PowerDnsConfig>,
+    /// Cloudflare accounts
+    #[serde(default)]
+    cloudflare: Vec<crate::CloudflareConfig>,
+    /// How DNS changes are checked before ACME validation
+    #[serde(default)]
+    dns_check: crate::DnsCheckConfig,
     certificate_requests: Vec<CertReqConfig>,
 }
 
+impl ConfigToml {
+    fn default_verify_cname() -> bool {
+        true
+    }
+}
+
+#[allow(clippy::needless_lifetimes)]
 impl Config {
     pub fn new_with_credentials(new_cred: instant_acme::AccountCredentials) -> String {
         let new_toml = NewConfigToml {
@@ -61,10 +129,32 @@ impl Config {
         // Parse config toml
         let ConfigToml {
             credential,
-            cname,
+            mut cname,
+            keep_challenge_records,
+            discover_cname,
+            verify_cname,
+            rfc2136,
+            acme_dns,
+            dns_responder,
+            exec,
+            powerdns,
+            cloudflare,
+            dns_check,
             certificate_requests,
         } = toml::from_str::<ConfigToml>(cfg_toml_str)?;
 
+        // acme-dns accounts imply CNAME, unless explicitly configured
+        for (hostname, account) in acme_dns.iter().flat_map(|cfg| cfg.accounts.iter()) {
+            cname
+                .entry(format!("_acme-challenge.{}", hostname))
+                .or_insert_with(|| account.fulldomain.clone());
+        }
+
+        for crt_req in &certificate_requests {
+            crt_req.validate()?;
+        }
+        dns_check.validate()?;
+
         // Load Account from credentials
         let account = instant_acme::Account::builder()?
             .from_credentials(credential)
@@ -72,7 +162,17 @@ impl Config {
 
         Ok(Self {
             account,
-            cname,
+            cname: cname.try_into()?,
+            keep_challenge_records,
+            discover_cname,
+            verify_cname,
+            rfc2136,
+            acme_dns,
+            dns_responder,
+            exec,
+            powerdns,
+            cloudflare,
+            dns_check,
             cert_requests: certificate_requests,
         })
     }
@@ -85,19 +185,108 @@ impl Config {
         self.cert_requests.iter()
     }
 
-    pub fn canonical_host<'a: 'c, 'b: 'c, 'c>(&'a self, hostname: &'b str) -> &'c str {
-        if let Some(cname) = self.cname.get(hostname) {
-            cname.as_str()
-        } else {
-            hostname
+    /// Whether challenge TXT records are kept after validation
+    pub fn keep_challenge_records(&self) -> bool {
+        self.keep_challenge_records
+    }
+
+    /// Whether CNAME of challenge records is looked up on DNS
+    pub fn discover_cname(&self) -> bool {
+        self.discover_cname
+    }
+
+    /// Whether CNAME of cname table is checked on DNS
+    pub fn verify_cname(&self) -> bool {
+        self.verify_cname
+    }
+
+    /// How DNS changes are checked before ACME validation
+    pub fn dns_check(&self) -> &crate::DnsCheckConfig {
+        &self.dns_check
+    }
+
+    /// DNS providers configured in acme.toml, other than AWS.
+    /// The built-in DNS responder starts listening here.
+    pub async fn dns_providers(&self) -> Result<Vec<Box<dyn crate::DnsProvider>>, Error> {
+        let mut providers = Vec::<Box<dyn crate::DnsProvider>>::new();
+        for cloudflare in &self.cloudflare {
+            providers.push(Box::new(crate::CloudflareProvider::new(cloudflare)?));
+        }
+        for powerdns in &self.powerdns {
+            providers.push(Box::new(crate::PowerDnsProvider::new(powerdns)?));
+        }
+        for rfc2136 in &self.rfc2136 {
+            providers.push(Box::new(crate::Rfc2136Provider::new(rfc2136)?));
+        }
+        for acme_dns in &self.acme_dns {
+            providers.push(Box::new(crate::AcmeDnsProvider::new(acme_dns)?));
         }
+        for exec in &self.exec {
+            providers.push(Box::new(crate::ExecProvider::new(exec)));
+        }
+        if let Some(dns_responder) = &self.dns_responder {
+            providers.push(Box::new(crate::DnsResponder::bind(dns_responder).await?));
+        }
+        Ok(providers)
+    }
+
+    pub fn canonical_host(&self, hostname: &str) -> String {
+        self.cname_of(hostname)
+            .unwrap_or_else(|| hostname.to_string())
+    }
+
+    /// Delegation of the challenge record in cname table
+    pub fn cname_of(&self, hostname: &str) -> Option<String> {
+        self.cname.get(hostname)
     }
 }
 
+#[allow(clippy::needless_lifetimes, clippy::needless_borrow)]
 impl CertReqConfig {
-    /// File name of input CSR (certificate signing request)
-    pub fn csr_file_name<'a>(&'a self) -> &'a str {
-        self.csr_file.as_str()
+    /// Either csr_file or domains must be specified
+    fn validate(&self) -> Result<(), Error> {
+        match (&self.csr_file, self.domains.is_empty(), &self.out_key_file) {
+            (Some(_), true, _) => Ok(()),
+            (None, false, Some(_)) => Ok(()),
+            (Some(_), false, _) => Err(Error::InvalidCertRequest(format!(
+                "{}: both csr_file and domains are specified",
+                self.out_crt_file
+            ))),
+            (None, true, _) => Err(Error::InvalidCertRequest(format!(
+                "{}: csr_file or domains is required",
+                self.out_crt_file
+            ))),
+            (None, false, None) => Err(Error::InvalidCertRequest(format!(
+                "{}: out_key_file is required with domains",
+                self.out_crt_file
+            ))),
+        }
+    }
+
+    /// File name of input CSR (certificate signing request),
+    /// None if CSR is generated from domains
+    pub fn csr_file_name<'a>(&'a self) -> Option<&'a str> {
+        self.csr_file.as_deref()
+    }
+
+    /// Hostnames of generated CSR
+    pub fn domains(&self) -> &[String] {
+        self.domains.as_slice()
+    }
+
+    /// Private key algorithm of generated CSR
+    pub fn key_type(&self) -> crate::KeyType {
+        self.key_type
+    }
+
+    /// File name (or S3 URL) of generated private key
+    pub fn key_file_name(&self) -> Option<&str> {
+        self.out_key_file.as_deref()
+    }
+
+    /// Whether the existing private key is reused
+    pub fn reuse_key(&self) -> bool {
+        self.reuse_key
     }
 
     /// File name (or S3 URL) of issued server certificate
@@ -110,6 +299,23 @@ impl CertReqConfig {
         self.out_iss_file.as_deref()
     }
 
+    /// Days before expiration to renew, None to renew every time
+    pub fn renew_before_days(&self) -> Option<u32> {
+        self.renew_before_days
+    }
+
+    /// Zone restriction of the hostname's challenge record, hostname setting first
+    pub fn zone_pin(&self, hostname: &str) -> crate::DnsZonePin {
+        let pin = crate::DnsZonePin {
+            hosted_zone_id: self.hosted_zone_id.clone(),
+            dns_provider: self.dns_provider.clone(),
+        };
+        match self.hostnames.get(hostname) {
+            Some(hostname_pin) => pin.merge(hostname_pin),
+            None => pin,
+        }
+    }
+
     pub fn extra_config<'de, T: serde::Deserialize<'de>>(self) -> Result<T, Error> {
         let extra = T::deserialize(serde::de::value::MapDeserializer::new(
             self.extra.into_iter(),
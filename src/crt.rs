@@ -0,0 +1,132 @@
+//! X.509 certificate issued by ACME server
+use crate::Error;
+
+#[derive(Clone)]
+pub struct X509Crt {
+    der_bytes: Vec<u8>,
+    subject: Option<String>,
+    alt_names: Vec<String>,
+    not_after: i64,
+    not_after_string: String,
+    serial: Vec<u8>,
+    authority_key_identifier: Option<Vec<u8>>,
+}
+
+impl X509Crt {
+    /// Read the first certificate in PEM encoded bytes
+    pub fn from_pem(crt_pem: &[u8]) -> Result<Self, Error> {
+        // Parse PEM
+        let (_rem, pem) = x509_parser::pem::parse_x509_pem(crt_pem)?;
+
+        Self::try_from(pem.contents)
+    }
+
+    /// Return all subjects in this certificate
+    pub fn subjects(&self) -> impl Iterator<Item = &str> {
+        self.subject
+            .iter()
+            .chain(self.alt_names.iter())
+            .map(|subj| subj.as_str())
+    }
+
+    /// Expiration time in UNIX timestamp
+    pub fn not_after(&self) -> i64 {
+        self.not_after
+    }
+
+    /// Expiration time in human readable form
+    pub fn not_after_string(&self) -> &str {
+        self.not_after_string.as_str()
+    }
+
+    /// Serial number in colon separated hex
+    pub fn serial_hex(&self) -> String {
+        self.serial
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<Vec<_>>()
+            .join(":")
+    }
+
+    /// Remaining days until expiration, negative if already expired
+    pub fn days_to_expiration(&self) -> i64 {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+        (self.not_after - now).div_euclid(24 * 60 * 60)
+    }
+
+    /// Identifier for ACME Renewal Information (RFC 9773),
+    /// None if the certificate has no authority key identifier
+    pub fn certificate_identifier(&self) -> Option<instant_acme::CertificateIdentifier<'static>> {
+        use base64::engine::{general_purpose::URL_SAFE_NO_PAD, Engine};
+
+        self.authority_key_identifier
+            .as_ref()
+            .map(|aki| instant_acme::CertificateIdentifier {
+                authority_key_identifier: URL_SAFE_NO_PAD.encode(aki).into(),
+                serial: URL_SAFE_NO_PAD.encode(&self.serial).into(),
+            })
+    }
+
+    pub fn der_bytes(&self) -> &[u8] {
+        self.der_bytes.as_slice()
+    }
+}
+
+impl TryFrom<Vec<u8>> for X509Crt {
+    type Error = Error;
+
+    /// Parse certificate
+    fn try_from(der_bytes: Vec<u8>) -> Result<Self, Self::Error> {
+        use x509_parser::extensions::ParsedExtension;
+        use x509_parser::{certificate::X509Certificate, nom, prelude::FromDer};
+
+        let (_rem, crt) = X509Certificate::from_der(&der_bytes)?;
+
+        // get subject common name, it may be omitted.
+        let subject = crt
+            .subject()
+            .iter_common_name()
+            .next()
+            .map(|cn| cn.as_str().map(|s| s.to_ascii_lowercase()))
+            .transpose()
+            .map_err(nom::Err::Error)?;
+
+        // Parse SubjectAltName extension
+        let mut alt_names = Vec::<String>::new();
+        if let Some(alt_name_ext) = crt.subject_alternative_name().map_err(nom::Err::Error)? {
+            use x509_parser::extensions::GeneralName;
+            for alt_name in &alt_name_ext.value.general_names {
+                if let GeneralName::DNSName(dns_name) = alt_name {
+                    alt_names.push(dns_name.to_ascii_lowercase());
+                }
+            }
+        }
+
+        // AuthorityKeyIdentifier extension, keyIdentifier field
+        let authority_key_identifier = crt.iter_extensions().find_map(|ext| {
+            if let ParsedExtension::AuthorityKeyIdentifier(aki) = ext.parsed_extension() {
+                aki.key_identifier.as_ref().map(|key_id| key_id.0.to_vec())
+            } else {
+                None
+            }
+        });
+
+        let not_after = crt.validity().not_after.timestamp();
+        let not_after_string = crt.validity().not_after.to_string();
+        // DER encoded serial number, not integer value
+        let serial = crt.raw_serial().to_vec();
+
+        Ok(Self {
+            der_bytes,
+            subject,
+            alt_names,
+            not_after,
+            not_after_string,
+            serial,
+            authority_key_identifier,
+        })
+    }
+}
@@ -8,6 +8,7 @@ pub struct X509Csr {
     alt_names: Vec<String>,
 }
 
+#[allow(clippy::needless_lifetimes)]
 impl X509Csr {
     /// Read certificate request from PEM encoded file
     pub fn from_pem_file<P: AsRef<std::path::Path>>(csr_pem_file: P) -> Result<Self, Error> {
@@ -24,6 +25,12 @@ impl X509Csr {
         Self::try_from(pem.contents)
     }
 
+    /// Create certificate request signed by the private key
+    pub fn generate(domains: &[String], private_key: &crate::PrivateKey) -> Result<Self, Error> {
+        let der_bytes = private_key.create_csr_der(domains)?;
+        Self::try_from(der_bytes)
+    }
+
     /// Return all subjects in this CSR
     pub fn subjects<'a>(&'a self) -> impl Iterator<Item = &'a str> {
         std::iter::once(self.subject.as_str())
@@ -35,6 +42,7 @@ impl X509Csr {
     }
 }
 
+#[allow(clippy::redundant_closure)]
 impl TryFrom<Vec<u8>> for X509Csr {
     type Error = Error;
 
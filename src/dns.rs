@@ -1,5 +1,88 @@
 use crate::Error;
 
+mod acme_dns;
+mod aws_retry;
+mod check;
+mod cloudflare;
+mod exec;
+mod http;
+mod lightsail;
+mod powerdns;
+mod responder;
+mod rfc2136;
+mod route53;
+
+pub use acme_dns::{AcmeDnsAccount, AcmeDnsConfig, AcmeDnsProvider};
+pub(crate) use check::CnameResolver;
+pub use check::{DnsCheckConfig, DnsProtocol};
+pub use cloudflare::{CloudflareConfig, CloudflareProvider};
+pub use exec::{ExecConfig, ExecProvider};
+pub use lightsail::LightsailProvider;
+pub use powerdns::{PowerDnsConfig, PowerDnsProvider};
+pub use responder::{DnsResponder, DnsResponderConfig};
+pub use rfc2136::{Rfc2136Config, Rfc2136Provider, TsigAlgorithm};
+pub use route53::Route53Provider;
+
+/// Boxed future returned by [`DnsProvider`] methods
+pub type DnsFuture<'a, T> = futures::future::BoxFuture<'a, Result<T, Error>>;
+
+/// DNS backend that can publish ACME challenge TXT records
+///
+/// Route53, Lightsail, Cloudflare, PowerDNS, RFC 2136, acme-dns, exec hook and
+/// the built-in responder are built in. Other backends can be added by implementing this trait and
+/// passing them to [`AllDnsZones::load_with_providers`].
+pub trait DnsProvider: Send + Sync {
+    /// Provider name, used for logging
+    fn name(&self) -> &str;
+
+    /// List all zones this provider can manage
+    fn list_zones(&self) -> DnsFuture<'_, Vec<DnsZone>>;
+
+    /// Names of NS records in the zone, for providers whose [`Self::list_zones`] leaves
+    /// delegations out because listing them is expensive. Called only for zones that may
+    /// contain a challenge record. None if [`Self::list_zones`] already set them.
+    fn list_delegations<'a>(&'a self, _zone: &'a DnsZone) -> Option<DnsFuture<'a, Vec<String>>> {
+        None
+    }
+
+    /// Create or replace the TXT record in the zone.
+    /// All values are published together as one RRset.
+    fn upsert_txt<'a>(
+        &'a self,
+        zone: &'a DnsZone,
+        record_name: &'a str,
+        txt_values: &'a [String],
+    ) -> DnsFuture<'a, DnsChangeInitialWait>;
+
+    /// Delete the TXT record values from the zone
+    fn delete_txt<'a>(
+        &'a self,
+        zone: &'a DnsZone,
+        record_name: &'a str,
+        txt_values: &'a [String],
+    ) -> DnsFuture<'a, ()>;
+
+    /// Wait until the provider reports the change as applied.
+    /// Called only for [`DnsChangeInitialWait::ChangeId`].
+    fn wait_for_change<'a>(
+        &'a self,
+        _change_id: &'a str,
+        _timeout: std::time::Duration,
+    ) -> DnsFuture<'a, ()> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /// TXT values that the provider's name servers answer, asked through the provider API.
+    /// None if not supported.
+    fn test_dns_answer<'a>(
+        &'a self,
+        _zone: &'a DnsZone,
+        _record_name: &'a str,
+    ) -> Option<DnsFuture<'a, Vec<String>>> {
+        None
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct AwsClient {
     lightsail_client: aws_sdk_lightsail::Client,
@@ -8,365 +91,396 @@ pub struct AwsClient {
 
 impl AwsClient {
     pub async fn new() -> Self {
+        use aws_sdk_route53::config::retry::RetryConfig;
+
         // DNS is global resource, end points are located at us-east-1
         let aws_sdk_config = crate::aws_config::aws_config_from_env("us-east-1").await;
+        // Retried by providers with rate limit, not by SDK
+        let lightsail_config = aws_sdk_lightsail::config::Builder::from(&aws_sdk_config)
+            .retry_config(RetryConfig::disabled())
+            .build();
+        let route53_config = aws_sdk_route53::config::Builder::from(&aws_sdk_config)
+            .retry_config(RetryConfig::disabled())
+            .build();
         Self {
-            lightsail_client: aws_sdk_lightsail::Client::new(&aws_sdk_config),
-            route53_client: aws_sdk_route53::Client::new(&aws_sdk_config),
+            lightsail_client: aws_sdk_lightsail::Client::from_conf(lightsail_config),
+            route53_client: aws_sdk_route53::Client::from_conf(route53_config),
         }
     }
 }
 
 pub struct AllDnsZones {
     dns_zones: Vec<DnsZone>,
-    aws_client: AwsClient,
+    providers: Vec<Box<dyn DnsProvider>>,
+    dns_check: DnsCheckConfig,
 }
 
 impl AllDnsZones {
+    /// Load Lightsail and Route53 zones that current AWS credential can manage
     pub async fn load(aws_clinet: &AwsClient) -> Result<Self, Error> {
-        let lightsail_fut = Self::list_lightsail_zones(&aws_clinet.lightsail_client);
-        let route53_fut = Self::list_route53_zones(&aws_clinet.route53_client);
+        Self::load_with_providers(Self::aws_providers(aws_clinet)).await
+    }
+
+    /// Lightsail and Route53 providers
+    pub fn aws_providers(aws_clinet: &AwsClient) -> Vec<Box<dyn DnsProvider>> {
+        vec![
+            Box::new(LightsailProvider::new(aws_clinet.lightsail_client.clone())),
+            Box::new(Route53Provider::new(aws_clinet.route53_client.clone())),
+        ]
+    }
 
-        // concurrent execution of lightsail and route53
-        let (lightsail_zones, route53_zones) = futures::join!(lightsail_fut, route53_fut);
+    /// Load zones from arbitrary DNS providers
+    pub async fn load_with_providers(providers: Vec<Box<dyn DnsProvider>>) -> Result<Self, Error> {
+        // concurrent execution of all providers
+        let results =
+            futures::future::join_all(providers.iter().map(|provider| provider.list_zones())).await;
 
         // Continue processing even if an error occurs.
-        // It's sufficient if either Lightsail or Route53 succeeds.
-        let lightsail_zones = lightsail_zones
-            .inspect_err(|e| log::warn!("{:?}", e))
-            .unwrap_or_default();
-        let route53_zones = route53_zones
-            .inspect_err(|e| log::warn!("{:?}", e))
-            .unwrap_or_default();
-
-        // Concat Lightsail DNS + Route53 zones
-        let mut all_zones = lightsail_zones;
-        all_zones.extend(route53_zones);
+        // It's sufficient if any of providers succeeds.
+        let mut all_zones = Vec::<DnsZone>::new();
+        for (provider_idx, zones) in results.into_iter().enumerate() {
+            let zones = zones
+                .inspect_err(|e| log::warn!("{}: {:?}", providers[provider_idx].name(), e))
+                .unwrap_or_default();
+            all_zones.extend(zones.into_iter().map(|mut zone| {
+                zone.provider_idx = provider_idx;
+                zone
+            }));
+        }
 
         if log::log_enabled!(log::Level::Debug) {
             for zone in &all_zones {
-                log::debug!("Zone: {}", zone.domain_name());
+                log::debug!(
+                    "Zone: {} ({})",
+                    zone.domain_name(),
+                    providers[zone.provider_idx].name()
+                );
             }
         }
 
         Ok(Self {
             dns_zones: all_zones,
-            aws_client: aws_clinet.clone(),
+            providers,
+            dns_check: DnsCheckConfig::default(),
         })
     }
 
+    /// Set how DNS changes are checked
+    pub fn with_dns_check(mut self, dns_check: DnsCheckConfig) -> Self {
+        self.dns_check = dns_check;
+        self
+    }
+
+    /// List delegations of the zones the hostname may be in, if not listed yet.
+    /// [`Self::find_zone`] ignores delegations of zones not loaded by this.
+    pub async fn load_delegations(&self, hostname: &str) {
+        let loading = self
+            .dns_zones
+            .iter()
+            .filter(|zone| zone.delegations.get().is_none() && zone.contains(hostname))
+            .filter_map(|zone| {
+                let provider = self.provider(zone);
+                let delegations = provider.list_delegations(zone)?;
+                Some(async move {
+                    match delegations.await {
+                        Ok(ns_names) => zone.set_delegations(ns_names),
+                        Err(e) => {
+                            log::warn!(
+                                "{}: failed to list NS records in {}: {:?}",
+                                provider.name(),
+                                zone.domain_name(),
+                                e
+                            );
+                            zone.set_delegations(Vec::<String>::new());
+                        }
+                    }
+                })
+            });
+        futures::future::join_all(loading).await;
+    }
+
     pub fn find_zone<'a>(&'a self, hostname: &str) -> Option<&'a DnsZone> {
+        self.find_pinned_zone(hostname, &DnsZonePin::default())
+    }
+
+    /// Same as [`Self::find_zone`], among the zones matching the pin
+    pub fn find_pinned_zone<'a>(&'a self, hostname: &str, pin: &DnsZonePin) -> Option<&'a DnsZone> {
         self.dns_zones
             .iter()
             .filter(|zone| zone.contains(hostname))
+            .filter(|zone| pin.matches(zone, self.provider(zone)))
             .max_by_key(|zone| zone.domain_name().len())
             .inspect(|zone| log::debug!("{} is in {}", hostname, zone.domain_name()))
     }
 
+    /// DNS provider which manages the zone
+    pub fn provider(&self, zone: &DnsZone) -> &dyn DnsProvider {
+        self.providers[zone.provider_idx].as_ref()
+    }
+
+    /// Publish all values as TXT record, replacing the existing one
     pub async fn update_txt_record<'a: 'c, 'b: 'c, 'c>(
         &'a self,
         record_name: &'b str,
-        txt_value: &'b str,
+        txt_values: &'b [String],
     ) -> Result<DnsChange<'c>, Error> {
-        match self.find_zone(record_name) {
-            Some(DnsZone::Lightsail {
-                domain_name,
-                txt_record_ids,
-            }) => {
-                log::info!(
-                    "Updating Lightsail TXT record {}.{}",
-                    record_name,
-                    domain_name
-                );
-                let initial_wait = DnsZone::update_txt_lightsail(
-                    &self.aws_client.lightsail_client,
-                    domain_name.as_str(),
-                    txt_record_ids,
-                    record_name,
-                    txt_value,
-                )
-                .await?;
-                log::info!("{}.{} was updated.", record_name, domain_name);
-
-                Ok(DnsChange {
-                    record_name,
-                    txt_value,
-                    initial_wait,
-                })
-            }
-            Some(DnsZone::Route53 {
-                domain_name,
-                hosted_zone_id,
-            }) => {
-                log::info!(
-                    "Updating Route53 TXT record {}.{}",
-                    record_name,
-                    domain_name
-                );
-                let initial_wait = DnsZone::update_txt_route53(
-                    &self.aws_client.route53_client,
-                    hosted_zone_id.as_str(),
-                    record_name,
-                    txt_value,
-                )
-                .await?;
-                log::info!("{}.{} was updated.", record_name, domain_name);
-
-                Ok(DnsChange {
-                    record_name,
-                    txt_value,
-                    initial_wait,
-                })
-            }
-            None => Err(Error::NoDnsZone(record_name.to_string())),
-        }
+        self.update_pinned_txt_record(record_name, txt_values, &DnsZonePin::default())
+            .await
     }
 
-    /// List all Lightsail DNS zones that AWS IAM role can access
-    async fn list_lightsail_zones(
-        client: &aws_sdk_lightsail::Client,
-    ) -> Result<Vec<DnsZone>, Error> {
-        // Call Lightsail GetDomains API
-        let resp = client.get_domains().send().await?;
-        let domains = resp.domains();
-        // AWS SDK response -> DnsZone::Lightsail
-        let dns_zones = domains.iter().filter_map(|domain| {
-            domain.name().map(|domain_name| {
-                // Collect entry-ID mapping
-                let txt_record_ids = domain
-                    .domain_entries()
-                    .into_iter()
-                    .filter_map(|entry| {
-                        if let (Some(name), Some(id)) = (entry.name(), entry.id()) {
-                            if entry.r#type.as_deref() == Some("TXT") {
-                                Some((name.to_ascii_lowercase(), id.to_string()))
-                            } else {
-                                None
-                            }
-                        } else {
-                            None
-                        }
-                    })
-                    .collect::<std::collections::HashMap<_, _>>();
+    /// Same as [`Self::update_txt_record`], in the zone matching the pin
+    pub async fn update_pinned_txt_record<'a: 'c, 'b: 'c, 'c>(
+        &'a self,
+        record_name: &'b str,
+        txt_values: &'b [String],
+        pin: &DnsZonePin,
+    ) -> Result<DnsChange<'c>, Error> {
+        let zone = self
+            .find_pinned_zone(record_name, pin)
+            .ok_or_else(|| Error::NoDnsZone(record_name.to_string()))?;
+        let provider = self.provider(zone);
+
+        log::info!(
+            "Updating {} TXT record {}.{}",
+            provider.name(),
+            record_name,
+            zone.domain_name()
+        );
+        let initial_wait = provider.upsert_txt(zone, record_name, txt_values).await?;
+        log::info!("{}.{} was updated.", record_name, zone.domain_name());
+
+        Ok(DnsChange {
+            record_name,
+            zone,
+            txt_values,
+            initial_wait,
+            provider,
+            dns_check: &self.dns_check,
+        })
+    }
 
-                DnsZone::Lightsail {
-                    domain_name: domain_name.to_ascii_lowercase(),
-                    txt_record_ids,
-                }
-            })
-        });
+    pub async fn delete_txt_record(
+        &self,
+        record_name: &str,
+        txt_values: &[String],
+    ) -> Result<(), Error> {
+        self.delete_pinned_txt_record(record_name, txt_values, &DnsZonePin::default())
+            .await
+    }
 
-        Ok(dns_zones.collect::<Vec<_>>())
+    /// Same as [`Self::delete_txt_record`], in the zone matching the pin
+    pub async fn delete_pinned_txt_record(
+        &self,
+        record_name: &str,
+        txt_values: &[String],
+        pin: &DnsZonePin,
+    ) -> Result<(), Error> {
+        let zone = self
+            .find_pinned_zone(record_name, pin)
+            .ok_or_else(|| Error::NoDnsZone(record_name.to_string()))?;
+        let provider = self.provider(zone);
+
+        log::info!(
+            "Deleting {} TXT record {}.{}",
+            provider.name(),
+            record_name,
+            zone.domain_name()
+        );
+        provider.delete_txt(zone, record_name, txt_values).await?;
+        log::info!("{}.{} was deleted.", record_name, zone.domain_name());
+
+        Ok(())
     }
+}
 
-    /// List all Route53 hosted zones that AWS IAM role can access
-    async fn list_route53_zones(client: &aws_sdk_route53::Client) -> Result<Vec<DnsZone>, Error> {
-        // Call Route53 ListHostedZones API
-        let pagenator = client
-            .list_hosted_zones()
-            .into_paginator()
-            .send()
-            .try_collect()
-            .await?;
-        // AWS SDK response -> DnsZone::Route53
-        let dns_zones = pagenator
-            .into_iter()
-            .map(|page| page.hosted_zones)
-            .flatten()
-            .map(|zone| DnsZone::Route53 {
-                // ListHostedZones returns zone name with '.' suffix, remove it.
-                domain_name: zone.name.trim_end_matches('.').to_ascii_lowercase(),
-                hosted_zone_id: zone.id,
-            });
+/// Restricts the zone of challenge records, instead of the longest suffix match among all zones
+#[derive(Clone, Debug, Default, Eq, PartialEq, serde::Deserialize)]
+pub struct DnsZonePin {
+    /// Zone ID, e.g. Route53 hosted zone id with or without "/hostedzone/"
+    pub hosted_zone_id: Option<String>,
+    /// Provider name, e.g. "Route53", "Lightsail"
+    pub dns_provider: Option<String>,
+}
 
-        Ok(dns_zones.collect::<Vec<_>>())
+impl DnsZonePin {
+    fn matches(&self, zone: &DnsZone, provider: &dyn DnsProvider) -> bool {
+        let strip = |id: &str| id.trim_start_matches("/hostedzone/").to_string();
+        self.hosted_zone_id
+            .as_deref()
+            .is_none_or(|id| strip(id) == strip(zone.zone_id()))
+            && self
+                .dns_provider
+                .as_deref()
+                .is_none_or(|name| name.eq_ignore_ascii_case(provider.name()))
+    }
+
+    /// Each field of other overrides this one
+    pub fn merge(&self, other: &DnsZonePin) -> DnsZonePin {
+        DnsZonePin {
+            hosted_zone_id: other
+                .hosted_zone_id
+                .clone()
+                .or_else(|| self.hosted_zone_id.clone()),
+            dns_provider: other
+                .dns_provider
+                .clone()
+                .or_else(|| self.dns_provider.clone()),
+        }
     }
 }
 
-#[derive(Clone, Eq, PartialEq)]
-pub enum DnsZone {
-    Lightsail {
-        domain_name: String,
-        txt_record_ids: std::collections::HashMap<String, String>,
-    },
-    Route53 {
-        domain_name: String,
-        hosted_zone_id: String,
-    },
+/// DNS zone managed by a [`DnsProvider`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DnsZone {
+    domain_name: String,
+    zone_id: String,
+    /// Subdomains delegated to other name servers by NS records, unset until listed
+    delegations: std::sync::OnceLock<Vec<String>>,
+    provider_idx: usize,
 }
 
 impl DnsZone {
-    pub fn domain_name<'a>(&'a self) -> &'a str {
-        match self {
-            Self::Lightsail {
-                domain_name,
-                txt_record_ids: _,
-            } => domain_name.as_str(),
-            Self::Route53 {
-                domain_name,
-                hosted_zone_id: _,
-            } => domain_name.as_str(),
+    /// `zone_id` is a provider specific identifier, e.g. Route53 hosted zone ID
+    pub fn new(domain_name: &str, zone_id: &str) -> Self {
+        Self {
+            domain_name: domain_name.trim_end_matches('.').to_ascii_lowercase(),
+            zone_id: zone_id.to_string(),
+            delegations: std::sync::OnceLock::new(),
+            provider_idx: 0,
         }
     }
 
-    /// Check if the hostname is in this zone
-    /// ToDo fix: if this domain has NS record, and hostname matches the NS record, it should be excluded
-    pub fn contains(&self, hostname: &str) -> bool {
-        let domain_name = self.domain_name();
-        hostname.ends_with(domain_name)
+    /// Set names of NS records in this zone, the apex NS record is ignored
+    pub fn with_delegations<S: AsRef<str>>(self, ns_names: impl IntoIterator<Item = S>) -> Self {
+        self.set_delegations(ns_names);
+        self
     }
 
-    async fn update_txt_lightsail(
-        client: &aws_sdk_lightsail::Client,
-        domain_name: &str,
-        txt_record_ids: &std::collections::HashMap<String, String>,
-        record_name: &str,
-        txt_value: &str,
-    ) -> Result<DnsChangeInitialWait<'static>, Error> {
-        let entry = aws_sdk_lightsail::types::DomainEntry::builder()
-            .name(record_name)
-            .r#type("TXT")
-            .target(format!("\"{}\"", txt_value));
-
-        if let Some(entry_id) = txt_record_ids.get(record_name) {
-            // record_name entry already exists, update it.
-            let _resp = client
-                .update_domain_entry()
-                .domain_name(domain_name)
-                .domain_entry(entry.id(entry_id).build())
-                .send()
-                .await?;
-
-            Ok(DnsChangeInitialWait::ConstTime(10))
-        } else {
-            // No entry exists, create new one
-            let _resp = client
-                .create_domain_entry()
-                .domain_name(domain_name)
-                .domain_entry(entry.build())
-                .send()
-                .await?;
-            // Lightsail DNS has long negatie cache TTL.
-            // To avoid NXDOMAIN caching, wait enough time after creation.
-            Ok(DnsChangeInitialWait::ConstTime(50))
-        }
+    /// Kept if already set
+    fn set_delegations<S: AsRef<str>>(&self, ns_names: impl IntoIterator<Item = S>) {
+        let delegations = ns_names
+            .into_iter()
+            .map(|name| name.as_ref().trim_end_matches('.').to_ascii_lowercase())
+            .filter(|name| name != &self.domain_name)
+            .collect();
+        let _ = self.delegations.set(delegations);
     }
 
-    async fn update_txt_route53<'a>(
-        client: &'a aws_sdk_route53::Client,
-        hosted_zone_id: &str,
-        record_name: &str,
-        txt_value: &str,
-    ) -> Result<DnsChangeInitialWait<'a>, Error> {
-        use aws_sdk_route53::types::{Change, ChangeAction, ChangeBatch};
-        use aws_sdk_route53::types::{ResourceRecord, ResourceRecordSet, RrType};
-
-        let record = ResourceRecordSet::builder()
-            .name(record_name)
-            .r#type(RrType::Txt)
-            .resource_records(
-                ResourceRecord::builder()
-                    .value(format!("\"{}\"", txt_value))
-                    .build()
-                    .unwrap(),
-            )
-            .ttl(60)
-            .build()
-            .unwrap(); // unwrap() is safe when .name() and .type() were called
-
-        let change = Change::builder()
-            .action(ChangeAction::Upsert)
-            .resource_record_set(record)
-            .build()
-            .unwrap(); // unwrap() is safe when .action() was called
-
-        let changes = ChangeBatch::builder().changes(change).build().unwrap(); // unwrap() is safe when .changes() was called
-
-        // Send change request
-        let resp = client
-            .change_resource_record_sets()
-            .hosted_zone_id(hosted_zone_id)
-            .change_batch(changes)
-            .send()
-            .await?;
-
-        if let Some(change_info) = resp.change_info() {
-            Ok(DnsChangeInitialWait::Route53Status(
-                change_info.id().to_string(),
-                client,
-            ))
-        } else {
-            // Unexpected, wait 50 seconds before validate DNS records
-            Ok(DnsChangeInitialWait::ConstTime(50))
-        }
+    pub fn domain_name(&self) -> &str {
+        self.domain_name.as_str()
+    }
+
+    pub fn zone_id(&self) -> &str {
+        self.zone_id.as_str()
+    }
+
+    /// Check if the hostname is in this zone, and not delegated to other name servers
+    pub fn contains(&self, hostname: &str) -> bool {
+        let hostname = hostname.trim_end_matches('.').to_ascii_lowercase();
+        is_subdomain_of(&hostname, self.domain_name())
+            && !self
+                .delegations
+                .get()
+                .into_iter()
+                .flatten()
+                .any(|delegation| is_subdomain_of(&hostname, delegation))
     }
 }
 
+/// Compare by labels, "notexample.com" is not a subdomain of "example.com"
+fn is_subdomain_of(hostname: &str, domain_name: &str) -> bool {
+    hostname
+        .strip_suffix(domain_name)
+        .is_some_and(|prefix| prefix.is_empty() || prefix.ends_with('.'))
+}
+
 pub struct DnsChange<'a> {
     record_name: &'a str,
-    txt_value: &'a str,
-    initial_wait: DnsChangeInitialWait<'a>,
+    zone: &'a DnsZone,
+    txt_values: &'a [String],
+    initial_wait: DnsChangeInitialWait,
+    provider: &'a dyn DnsProvider,
+    dns_check: &'a DnsCheckConfig,
 }
 
-enum DnsChangeInitialWait<'a> {
+/// How to wait for the DNS change before checking it on DNS
+pub enum DnsChangeInitialWait {
+    /// Wait for fixed seconds
     ConstTime(u32),
-    Route53Status(String, &'a aws_sdk_route53::Client),
+    /// Poll [`DnsProvider::wait_for_change`] with this change ID
+    ChangeId(String),
+    /// Already served by the provider itself, no need to check DNS
+    Published,
 }
 
 impl DnsChange<'_> {
+    /// Wait until all TXT values are visible on all authoritative name servers
     pub async fn wait_for_propergation(&self, timeout_secs: u32) -> Result<bool, Error> {
-        const POLLING_INTERVAL_SECS: u32 = 10;
-
-        let resolver = hickory_resolver::Resolver::builder_tokio()?.build();
+        const POLLING_INTERVAL_SECS: u64 = 10;
 
         let timeout = std::time::Duration::from_secs(timeout_secs as u64);
         let wait_start = std::time::Instant::now();
 
         match &self.initial_wait {
             DnsChangeInitialWait::ConstTime(init_wait_secs) => {
-                if POLLING_INTERVAL_SECS < *init_wait_secs {
-                    let init_wait = std::time::Duration::from_secs(
-                        (*init_wait_secs - POLLING_INTERVAL_SECS) as u64,
-                    );
-                    tokio::time::sleep(init_wait).await;
-                }
+                let init_wait = std::time::Duration::from_secs(*init_wait_secs as u64);
+                tokio::time::sleep(init_wait).await;
             }
-            DnsChangeInitialWait::Route53Status(change_id, client) => {
-                use aws_sdk_route53::types::ChangeStatus;
-                while wait_start.elapsed() < timeout {
-                    let resp = client.get_change().id(change_id).send().await?;
-                    if let Some(change_info) = resp.change_info() {
-                        if change_info.status() == &ChangeStatus::Insync {
-                            break;
-                        }
-                    }
-                    tokio::time::sleep(std::time::Duration::from_secs(
-                        POLLING_INTERVAL_SECS as u64,
-                    ))
-                    .await;
-                }
+            DnsChangeInitialWait::ChangeId(change_id) => {
+                self.provider.wait_for_change(change_id, timeout).await?;
+            }
+            DnsChangeInitialWait::Published => {
+                log::debug!("{} is served by {}", self.record_name, self.provider.name());
+                return Ok(true);
             }
         }
 
-        while wait_start.elapsed() < timeout {
-            tokio::time::sleep(std::time::Duration::from_secs(POLLING_INTERVAL_SECS as u64)).await;
-            println!("lookup {}", self.record_name);
-            resolver.clear_cache();
-            let records = resolver.txt_lookup(self.record_name).await;
-            println!("records {:?}", records);
-            if let Ok(records) = records {
-                for txt_record in records.iter() {
-                    for txt_data in txt_record.iter() {
-                        let txt_str = std::str::from_utf8(txt_data);
-                        println!("TXT value: {:?}", txt_str);
-                        if txt_str == Ok(self.txt_value) {
-                            return Ok(true);
-                        }
-                    }
-                }
+        // System resolver is not needed if the provider API answers
+        let use_test_dns_answer = self.dns_check.use_test_dns_answer
+            && self
+                .provider
+                .test_dns_answer(self.zone, self.record_name)
+                .is_some();
+        let propagation_check = if use_test_dns_answer {
+            None
+        } else {
+            Some(check::PropagationCheck::new(self.dns_check)?)
+        };
+
+        loop {
+            let visible = if let Some(check) = &propagation_check {
+                check
+                    .is_visible(self.zone.domain_name(), self.record_name, self.txt_values)
+                    .await
+            } else {
+                self.is_visible_on_provider().await
+            };
+            if visible {
+                return Ok(true);
             }
+
+            let interval = std::time::Duration::from_secs(POLLING_INTERVAL_SECS);
+            if timeout <= wait_start.elapsed() + interval {
+                return Err(Error::DnsUpdateTimeout);
+            }
+            tokio::time::sleep(interval).await;
         }
+    }
 
-        Err(Error::DnsUpdateTimeout)
+    async fn is_visible_on_provider(&self) -> bool {
+        let Some(test_dns_answer) = self.provider.test_dns_answer(self.zone, self.record_name)
+        else {
+            return false;
+        };
+        match test_dns_answer.await {
+            Ok(visible_values) => self
+                .txt_values
+                .iter()
+                .all(|value| visible_values.contains(value)),
+            Err(e) => {
+                log::warn!("{} TestDNSAnswer: {}", self.provider.name(), e);
+                false
+            }
+        }
     }
 }
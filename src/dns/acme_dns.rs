@@ -0,0 +1,134 @@
+//! acme-dns (https://github.com/joohoi/acme-dns) HTTP API
+use super::http::{api_base_url, HttpClient};
+use super::{DnsChangeInitialWait, DnsFuture, DnsProvider, DnsZone};
+use crate::Error;
+
+/// `[[acme_dns]]` section in acme.toml
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct AcmeDnsConfig {
+    /// API endpoint, e.g. "https://auth.acme-dns.io"
+    pub api_url: String,
+    /// Credentials by hostname of certificate
+    pub accounts: std::collections::HashMap<String, AcmeDnsAccount>,
+}
+
+/// Credential returned by acme-dns /register
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct AcmeDnsAccount {
+    pub username: String,
+    pub password: String,
+    pub subdomain: String,
+    /// CNAME target of _acme-challenge record
+    pub fulldomain: String,
+}
+
+pub struct AcmeDnsProvider {
+    api_url: url::Url,
+    accounts: Vec<AcmeDnsAccount>,
+    http_client: HttpClient,
+}
+
+impl AcmeDnsProvider {
+    pub fn new(config: &AcmeDnsConfig) -> Result<Self, Error> {
+        Ok(Self {
+            api_url: api_base_url(&config.api_url)?,
+            accounts: config.accounts.values().cloned().collect(),
+            http_client: HttpClient::new()?,
+        })
+    }
+
+    /// Register new account on acme-dns server.
+    /// `allow_from` restricts update API to the networks, e.g. "192.0.2.0/24".
+    pub async fn register(api_url: &str, allow_from: &[String]) -> Result<AcmeDnsAccount, Error> {
+        #[derive(serde::Serialize)]
+        struct RegisterRequest<'a> {
+            allowfrom: &'a [String],
+        }
+
+        let body = serde_json::to_vec(&RegisterRequest {
+            allowfrom: allow_from,
+        })?;
+        let url = api_base_url(api_url)?.join("register")?;
+        let response = HttpClient::new()?
+            .request(hyper::Method::POST, &url, &[], Some(body))
+            .await?;
+        Ok(serde_json::from_slice(&response)?)
+    }
+
+    async fn update_txt_acme_dns(&self, subdomain: &str, txt_value: &str) -> Result<(), Error> {
+        #[derive(serde::Serialize)]
+        struct UpdateRequest<'a> {
+            subdomain: &'a str,
+            txt: &'a str,
+        }
+
+        let account = self
+            .accounts
+            .iter()
+            .find(|account| account.subdomain == subdomain)
+            .ok_or_else(|| Error::NoDnsZone(subdomain.to_string()))?;
+
+        let body = serde_json::to_vec(&UpdateRequest {
+            subdomain,
+            txt: txt_value,
+        })?;
+        let url = self.api_url.join("update")?;
+        let headers = [
+            ("X-Api-User", account.username.as_str()),
+            ("X-Api-Key", account.password.as_str()),
+        ];
+        self.http_client
+            .request(hyper::Method::POST, &url, &headers, Some(body))
+            .await?;
+        Ok(())
+    }
+}
+
+impl DnsProvider for AcmeDnsProvider {
+    fn name(&self) -> &str {
+        "acme-dns"
+    }
+
+    fn list_zones(&self) -> DnsFuture<'_, Vec<DnsZone>> {
+        // Each account serves only one TXT record, its fulldomain
+        let zones = self
+            .accounts
+            .iter()
+            .map(|account| DnsZone::new(&account.fulldomain, &account.subdomain))
+            .collect::<Vec<_>>();
+        Box::pin(async move { Ok(zones) })
+    }
+
+    fn upsert_txt<'a>(
+        &'a self,
+        zone: &'a DnsZone,
+        _record_name: &'a str,
+        txt_values: &'a [String],
+    ) -> DnsFuture<'a, DnsChangeInitialWait> {
+        Box::pin(async move {
+            // acme-dns keeps only 2 latest values, enough for a host and its wildcard
+            if 2 < txt_values.len() {
+                log::warn!(
+                    "acme-dns keeps only 2 TXT values, {} given for {}",
+                    txt_values.len(),
+                    zone.domain_name()
+                );
+            }
+            for txt_value in txt_values {
+                self.update_txt_acme_dns(zone.zone_id(), txt_value).await?;
+            }
+            Ok(DnsChangeInitialWait::ConstTime(0))
+        })
+    }
+
+    fn delete_txt<'a>(
+        &'a self,
+        zone: &'a DnsZone,
+        _record_name: &'a str,
+        _txt_values: &'a [String],
+    ) -> DnsFuture<'a, ()> {
+        // acme-dns has no delete API, old values are rotated out by next update
+        log::debug!("acme-dns does not delete TXT of {}", zone.domain_name());
+        Box::pin(async { Ok(()) })
+    }
+}
@@ -0,0 +1,117 @@
+//! Retries and rate limit of AWS DNS API calls
+use aws_sdk_route53::config::http::HttpResponse;
+use aws_sdk_route53::error::{ProvideErrorMetadata, SdkError};
+use std::time::Duration;
+
+const MAX_ATTEMPTS: u32 = 6;
+const BASE_DELAY: Duration = Duration::from_millis(200);
+const MAX_DELAY: Duration = Duration::from_secs(20);
+
+/// Error codes of throttled requests, or conflicts with requests in progress
+const THROTTLING_CODES: &[&str] = &[
+    "Throttling",
+    "ThrottlingException",
+    "ThrottledException",
+    "RequestThrottled",
+    "RequestThrottledException",
+    "RequestLimitExceeded",
+    "TooManyRequestsException",
+    "PriorRequestNotComplete",
+];
+
+/// Error codes of server side failures
+const TRANSIENT_CODES: &[&str] = &[
+    "InternalError",
+    "InternalFailure",
+    "ServiceUnavailable",
+    "ServiceUnavailableException",
+    "RequestTimeout",
+    "RequestTimeoutException",
+];
+
+/// Shared by all calls to one AWS service
+pub(super) struct AwsRetry {
+    service: &'static str,
+    /// Minimum interval between requests
+    interval: Duration,
+    /// When the next request may be sent
+    next_slot: std::sync::Mutex<tokio::time::Instant>,
+}
+
+impl AwsRetry {
+    pub(super) fn new(service: &'static str, requests_per_sec: u32) -> Self {
+        Self {
+            service,
+            interval: Duration::from_secs(1) / requests_per_sec.max(1),
+            next_slot: std::sync::Mutex::new(tokio::time::Instant::now()),
+        }
+    }
+
+    /// Send the request, retry on throttling and transient errors.
+    /// Other errors, e.g. AccessDenied, are returned immediately.
+    pub(super) async fn call<T, E, F, Fut>(
+        &self,
+        operation: &str,
+        mut request: F,
+    ) -> Result<T, SdkError<E, HttpResponse>>
+    where
+        E: ProvideErrorMetadata,
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = Result<T, SdkError<E, HttpResponse>>>,
+    {
+        let mut attempt = 1;
+        loop {
+            self.wait_for_slot().await;
+            match request().await {
+                Err(e) if attempt < MAX_ATTEMPTS && is_retryable(&e) => {
+                    let delay = backoff_delay(attempt);
+                    log::warn!(
+                        "{} {} failed ({}), retry in {:?}",
+                        self.service,
+                        operation,
+                        e.code().unwrap_or("no error code"),
+                        delay
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Keep requests under the rate cap
+    async fn wait_for_slot(&self) {
+        let slot = {
+            let mut next_slot = self.next_slot.lock().unwrap();
+            let slot = (*next_slot).max(tokio::time::Instant::now());
+            *next_slot = slot + self.interval;
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+}
+
+fn is_retryable<E: ProvideErrorMetadata>(e: &SdkError<E, HttpResponse>) -> bool {
+    match e {
+        SdkError::TimeoutError(_) | SdkError::ResponseError(_) => true,
+        SdkError::DispatchFailure(failure) => failure.is_io() || failure.is_timeout(),
+        SdkError::ServiceError(context) => {
+            let status = context.raw().status().as_u16();
+            e.code().is_some_and(|code| {
+                THROTTLING_CODES.contains(&code) || TRANSIENT_CODES.contains(&code)
+            }) || status == 429
+                || (500..600).contains(&status)
+        }
+        _ => false,
+    }
+}
+
+/// Exponential backoff, randomized in the upper half to spread concurrent retries
+fn backoff_delay(attempt: u32) -> Duration {
+    let delay = BASE_DELAY
+        .saturating_mul(1 << attempt.min(16))
+        .min(MAX_DELAY);
+    let half_millis = delay.as_millis() as u64 / 2;
+    Duration::from_millis(half_millis + fastrand::u64(0..=half_millis))
+}
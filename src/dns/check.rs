@@ -0,0 +1,319 @@
+//! Check TXT records on authoritative name servers
+use crate::Error;
+use hickory_resolver::TokioResolver;
+
+/// `[dns_check]` section in acme.toml, how DNS changes are checked before ACME validation
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+pub struct DnsCheckConfig {
+    /// Recursive resolvers, e.g. "192.0.2.53" or "192.0.2.53:5353".
+    /// System config is used if empty.
+    /// With udp or tcp, they find name servers of zones, which are asked directly.
+    /// With tls or https, TXT records are checked through them instead,
+    /// nothing is sent to port 53 of authoritative servers.
+    #[serde(default)]
+    pub nameservers: Vec<String>,
+    /// Protocol to the recursive resolvers, tls and https need nameservers
+    #[serde(default)]
+    pub protocol: DnsProtocol,
+    /// Server name in the certificate of the resolvers, required for tls and https
+    pub tls_dns_name: Option<String>,
+    /// Timeout of each query
+    pub timeout_secs: Option<u64>,
+    /// Query these servers instead of the zone's NS records, for testing
+    #[serde(default)]
+    pub authoritative_servers: Vec<std::net::SocketAddr>,
+    /// Ask the provider API which values its name servers answer,
+    /// e.g. Route53 TestDNSAnswer, instead of querying DNS
+    #[serde(default)]
+    pub use_test_dns_answer: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DnsProtocol {
+    #[default]
+    Udp,
+    Tcp,
+    /// DNS over TLS (RFC 7858)
+    Tls,
+    /// DNS over HTTPS (RFC 8484)
+    Https,
+}
+
+impl DnsCheckConfig {
+    /// Check nameservers and TLS settings, called when config is loaded
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.protocol.is_encrypted() {
+            if self.nameservers.is_empty() {
+                return Err(Error::InvalidDnsCheckConfig(
+                    "nameservers are required for tls and https".to_string(),
+                ));
+            }
+            if self.tls_dns_name.is_none() {
+                return Err(Error::InvalidDnsCheckConfig(
+                    "tls_dns_name is required for tls and https".to_string(),
+                ));
+            }
+        }
+        for nameserver in &self.nameservers {
+            if parse_socket_addr(nameserver, self.protocol.default_port()).is_none() {
+                return Err(Error::InvalidDnsCheckConfig(nameserver.clone()));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl DnsProtocol {
+    fn default_port(self) -> u16 {
+        match self {
+            Self::Udp | Self::Tcp => 53,
+            Self::Tls => 853,
+            Self::Https => 443,
+        }
+    }
+
+    /// Needs tls_dns_name
+    fn is_encrypted(self) -> bool {
+        matches!(self, Self::Tls | Self::Https)
+    }
+}
+
+pub(super) struct PropagationCheck<'a> {
+    config: &'a DnsCheckConfig,
+    /// Recursive resolver to find name servers
+    resolver: TokioResolver,
+}
+
+impl<'a> PropagationCheck<'a> {
+    pub(super) fn new(config: &'a DnsCheckConfig) -> Result<Self, Error> {
+        Ok(Self {
+            config,
+            resolver: recursive_resolver(config)?,
+        })
+    }
+
+    /// Check if all authoritative name servers of the zone return all TXT values.
+    /// With tls or https, the recursive resolvers are asked instead.
+    pub(super) async fn is_visible(
+        &self,
+        zone_name: &str,
+        record_name: &str,
+        txt_values: &[String],
+    ) -> bool {
+        // Outbound port 53 may be blocked, cache of the resolver is disabled
+        if self.config.protocol.is_encrypted() && self.config.authoritative_servers.is_empty() {
+            let result = txt_lookup(&self.resolver, record_name).await;
+            return contains_all(result, record_name, "resolver", txt_values);
+        }
+
+        // Name servers may change while waiting, look up every time
+        let name_servers = match self.name_servers(zone_name).await {
+            Ok(name_servers) => name_servers,
+            Err(e) => {
+                log::warn!("Name servers of {}: {}", zone_name, e);
+                return false;
+            }
+        };
+
+        let results = futures::future::join_all(
+            name_servers
+                .iter()
+                .map(|server| self.txt_on_server(*server, record_name)),
+        )
+        .await;
+
+        !name_servers.is_empty()
+            && results
+                .into_iter()
+                .zip(&name_servers)
+                .all(|(result, server)| {
+                    contains_all(result, record_name, &server.to_string(), txt_values)
+                })
+    }
+
+    /// Addresses of the name servers of the zone
+    async fn name_servers(&self, zone_name: &str) -> Result<Vec<std::net::SocketAddr>, Error> {
+        if !self.config.authoritative_servers.is_empty() {
+            return Ok(self.config.authoritative_servers.clone());
+        }
+
+        // Zone of the provider may not be a zone on DNS, e.g. acme-dns subdomain
+        let mut domain = zone_name.trim_end_matches('.');
+        let ns_names = loop {
+            match self.resolver.ns_lookup(format!("{}.", domain)).await {
+                Ok(ns) if ns.iter().next().is_some() => break ns,
+                _ => match domain.split_once('.') {
+                    Some((_, parent)) if !parent.is_empty() => domain = parent,
+                    _ => return Err(Error::NoDnsZone(zone_name.to_string())),
+                },
+            }
+        };
+
+        let mut addrs = Vec::new();
+        for ns_name in ns_names.iter() {
+            match self.resolver.lookup_ip(ns_name.0.clone()).await {
+                Ok(ips) => addrs.extend(ips.iter().map(|ip| std::net::SocketAddr::new(ip, 53))),
+                Err(e) => log::warn!("Address of {}: {}", ns_name, e),
+            }
+        }
+        log::debug!("Name servers of {}: {:?}", domain, addrs);
+        Ok(addrs)
+    }
+
+    /// Query TXT record to the server, without recursion
+    async fn txt_on_server(
+        &self,
+        server: std::net::SocketAddr,
+        record_name: &str,
+    ) -> Result<Vec<String>, Error> {
+        use hickory_resolver::config::{NameServerConfigGroup, ResolverConfig};
+
+        let mut name_servers =
+            NameServerConfigGroup::from_ips_clear(&[server.ip()], server.port(), true);
+        if self.config.protocol == DnsProtocol::Tcp {
+            name_servers.retain(|ns| ns.protocol == hickory_resolver::proto::xfer::Protocol::Tcp);
+        }
+        let mut options = self.resolver.options().clone();
+        options.recursion_desired = false;
+        options.attempts = 1;
+        let resolver = TokioResolver::builder_with_config(
+            ResolverConfig::from_parts(None, Vec::new(), name_servers),
+            hickory_resolver::name_server::TokioConnectionProvider::default(),
+        )
+        .with_options(options)
+        .build();
+
+        txt_lookup(&resolver, record_name).await
+    }
+}
+
+/// Follows CNAME records to find where the challenge record is delegated
+pub(crate) struct CnameResolver(TokioResolver);
+
+impl CnameResolver {
+    pub(crate) fn new(config: &DnsCheckConfig) -> Result<Self, Error> {
+        Ok(Self(recursive_resolver(config)?))
+    }
+
+    /// Final target of CNAME chain, or the name itself if not CNAME
+    pub(crate) async fn resolve(&self, name: &str) -> Result<String, Error> {
+        use hickory_resolver::proto::rr::{RData, RecordType};
+        const MAX_CNAME_CHAIN: usize = 8;
+
+        let mut target = name.trim_end_matches('.').to_string();
+        for _ in 0..MAX_CNAME_CHAIN {
+            let lookup = match self
+                .0
+                .lookup(format!("{}.", target), RecordType::CNAME)
+                .await
+            {
+                Ok(lookup) => lookup,
+                Err(e) if e.is_no_records_found() => return Ok(target),
+                Err(e) => return Err(e.into()),
+            };
+            let next = lookup.record_iter().find_map(|record| match record.data() {
+                RData::CNAME(cname)
+                    if record
+                        .name()
+                        .to_ascii()
+                        .trim_end_matches('.')
+                        .eq_ignore_ascii_case(&target) =>
+                {
+                    Some(
+                        cname
+                            .0
+                            .to_ascii()
+                            .trim_end_matches('.')
+                            .to_ascii_lowercase(),
+                    )
+                }
+                _ => None,
+            });
+            match next {
+                Some(next) => {
+                    log::debug!("{} is CNAME to {}", target, next);
+                    target = next;
+                }
+                None => return Ok(target),
+            }
+        }
+        Err(Error::NoDnsZone(format!("{}: too long CNAME chain", name)))
+    }
+}
+
+/// Resolver configured by [dns_check], or by system config
+fn recursive_resolver(config: &DnsCheckConfig) -> Result<TokioResolver, Error> {
+    use hickory_resolver::config::{NameServerConfig, ResolverConfig};
+    use hickory_resolver::proto::xfer::Protocol;
+
+    config.validate()?;
+    let mut builder = if config.nameservers.is_empty() {
+        TokioResolver::builder_tokio()?
+    } else {
+        let protocol = match config.protocol {
+            DnsProtocol::Udp => Protocol::Udp,
+            DnsProtocol::Tcp => Protocol::Tcp,
+            DnsProtocol::Tls => Protocol::Tls,
+            DnsProtocol::Https => Protocol::Https,
+        };
+        let mut resolver_config = ResolverConfig::new();
+        for nameserver in &config.nameservers {
+            let socket_addr = parse_socket_addr(nameserver, config.protocol.default_port())
+                .ok_or_else(|| Error::InvalidDnsCheckConfig(nameserver.clone()))?;
+            let mut nameserver_config = NameServerConfig::new(socket_addr, protocol);
+            nameserver_config.tls_dns_name = config.tls_dns_name.clone();
+            resolver_config.add_name_server(nameserver_config);
+        }
+        TokioResolver::builder_with_config(
+            resolver_config,
+            hickory_resolver::name_server::TokioConnectionProvider::default(),
+        )
+    };
+    // Answers change while waiting
+    builder.options_mut().cache_size = 0;
+    if let Some(timeout_secs) = config.timeout_secs {
+        builder.options_mut().timeout = std::time::Duration::from_secs(timeout_secs);
+    }
+    Ok(builder.build())
+}
+
+async fn txt_lookup(resolver: &TokioResolver, record_name: &str) -> Result<Vec<String>, Error> {
+    let records = resolver
+        .txt_lookup(format!("{}.", record_name.trim_end_matches('.')))
+        .await?;
+    Ok(records
+        .iter()
+        .flat_map(|txt_record| txt_record.iter())
+        .map(|txt_data| String::from_utf8_lossy(txt_data).to_string())
+        .collect())
+}
+
+fn contains_all(
+    result: Result<Vec<String>, Error>,
+    record_name: &str,
+    server: &str,
+    txt_values: &[String],
+) -> bool {
+    match result {
+        Ok(visible_values) => {
+            log::debug!("{} on {}: {:?}", record_name, server, visible_values);
+            txt_values
+                .iter()
+                .all(|value| visible_values.contains(value))
+        }
+        Err(e) => {
+            log::debug!("{} on {}: {}", record_name, server, e);
+            false
+        }
+    }
+}
+
+/// "192.0.2.53", "192.0.2.53:5353", "2001:db8::53" or "[2001:db8::53]:5353"
+fn parse_socket_addr(addr: &str, default_port: u16) -> Option<std::net::SocketAddr> {
+    addr.parse::<std::net::SocketAddr>().ok().or_else(|| {
+        addr.parse::<std::net::IpAddr>()
+            .ok()
+            .map(|ip| std::net::SocketAddr::new(ip, default_port))
+    })
+}
@@ -0,0 +1,230 @@
+//! Cloudflare DNS API v4
+use super::http::{api_base_url, HttpClient};
+use super::{DnsChangeInitialWait, DnsFuture, DnsProvider, DnsZone};
+use crate::Error;
+
+/// `[[cloudflare]]` section in acme.toml
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct CloudflareConfig {
+    /// API token with Zone:Read and DNS:Edit permissions
+    pub api_token: String,
+    /// Override for testing
+    #[serde(default = "CloudflareConfig::default_api_url")]
+    pub api_url: String,
+}
+
+impl CloudflareConfig {
+    fn default_api_url() -> String {
+        "https://api.cloudflare.com/client/v4".to_string()
+    }
+}
+
+pub struct CloudflareProvider {
+    api_url: url::Url,
+    authorization: String,
+    http_client: HttpClient,
+}
+
+/// Common envelope of API responses
+#[derive(serde::Deserialize)]
+struct ApiResponse<T> {
+    result: T,
+    result_info: Option<ResultInfo>,
+}
+
+#[derive(serde::Deserialize)]
+struct ResultInfo {
+    page: u32,
+    total_pages: u32,
+}
+
+#[derive(serde::Deserialize)]
+struct Zone {
+    id: String,
+    name: String,
+}
+
+#[derive(serde::Deserialize)]
+struct DnsRecord {
+    id: String,
+    content: String,
+}
+
+impl CloudflareProvider {
+    pub fn new(config: &CloudflareConfig) -> Result<Self, Error> {
+        Ok(Self {
+            api_url: api_base_url(&config.api_url)?,
+            authorization: format!("Bearer {}", config.api_token),
+            http_client: HttpClient::new()?,
+        })
+    }
+
+    async fn api_request<T: serde::de::DeserializeOwned>(
+        &self,
+        method: hyper::Method,
+        url: url::Url,
+        body: Option<Vec<u8>>,
+    ) -> Result<ApiResponse<T>, Error> {
+        let headers = [("Authorization", self.authorization.as_str())];
+        let response = self
+            .http_client
+            .request(method, &url, &headers, body)
+            .await?;
+        Ok(serde_json::from_slice(&response)?)
+    }
+
+    async fn list_cloudflare_zones(&self) -> Result<Vec<DnsZone>, Error> {
+        let mut zones = Vec::new();
+        let mut page = 1;
+        loop {
+            let mut url = self.api_url.join("zones")?;
+            url.query_pairs_mut()
+                .append_pair("per_page", "50")
+                .append_pair("page", &page.to_string());
+            let response = self
+                .api_request::<Vec<Zone>>(hyper::Method::GET, url, None)
+                .await?;
+            zones.extend(
+                response
+                    .result
+                    .iter()
+                    .map(|zone| DnsZone::new(&zone.name, &zone.id)),
+            );
+
+            match response.result_info {
+                Some(info) if info.page < info.total_pages => page = info.page + 1,
+                _ => break,
+            }
+        }
+        Ok(zones)
+    }
+
+    async fn txt_records(&self, zone_id: &str, record_name: &str) -> Result<Vec<DnsRecord>, Error> {
+        let mut url = self
+            .api_url
+            .join(&format!("zones/{}/dns_records", zone_id))?;
+        url.query_pairs_mut()
+            .append_pair("type", "TXT")
+            .append_pair("name", record_name.trim_end_matches('.'))
+            .append_pair("per_page", "100");
+        let response = self
+            .api_request::<Vec<DnsRecord>>(hyper::Method::GET, url, None)
+            .await?;
+        Ok(response.result)
+    }
+
+    async fn create_txt_record(
+        &self,
+        zone_id: &str,
+        record_name: &str,
+        txt_value: &str,
+    ) -> Result<(), Error> {
+        #[derive(serde::Serialize)]
+        struct NewRecord<'a> {
+            #[serde(rename = "type")]
+            rr_type: &'a str,
+            name: &'a str,
+            content: String,
+            ttl: u32,
+        }
+
+        let body = serde_json::to_vec(&NewRecord {
+            rr_type: "TXT",
+            name: record_name.trim_end_matches('.'),
+            content: format!("\"{}\"", txt_value),
+            ttl: 60,
+        })?;
+        let url = self
+            .api_url
+            .join(&format!("zones/{}/dns_records", zone_id))?;
+        self.api_request::<serde_json::Value>(hyper::Method::POST, url, Some(body))
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_record(&self, zone_id: &str, record_id: &str) -> Result<(), Error> {
+        let url = self
+            .api_url
+            .join(&format!("zones/{}/dns_records/{}", zone_id, record_id))?;
+        self.api_request::<serde_json::Value>(hyper::Method::DELETE, url, None)
+            .await?;
+        Ok(())
+    }
+
+    /// Create missing values, then delete stale ones
+    async fn update_txt_cloudflare(
+        &self,
+        zone_id: &str,
+        record_name: &str,
+        txt_values: &[String],
+    ) -> Result<(), Error> {
+        let existing = self.txt_records(zone_id, record_name).await?;
+        for txt_value in txt_values {
+            if !existing
+                .iter()
+                .any(|record| txt_content(record) == txt_value)
+            {
+                self.create_txt_record(zone_id, record_name, txt_value)
+                    .await?;
+            }
+        }
+        for record in &existing {
+            if !txt_values.iter().any(|value| value == txt_content(record)) {
+                self.delete_record(zone_id, &record.id).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn delete_txt_cloudflare(
+        &self,
+        zone_id: &str,
+        record_name: &str,
+        txt_values: &[String],
+    ) -> Result<(), Error> {
+        let existing = self.txt_records(zone_id, record_name).await?;
+        for record in &existing {
+            if txt_values.iter().any(|value| value == txt_content(record)) {
+                self.delete_record(zone_id, &record.id).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// TXT content may or may not be quoted
+fn txt_content(record: &DnsRecord) -> &str {
+    record.content.trim_matches('"')
+}
+
+impl DnsProvider for CloudflareProvider {
+    fn name(&self) -> &str {
+        "Cloudflare"
+    }
+
+    fn list_zones(&self) -> DnsFuture<'_, Vec<DnsZone>> {
+        Box::pin(self.list_cloudflare_zones())
+    }
+
+    fn upsert_txt<'a>(
+        &'a self,
+        zone: &'a DnsZone,
+        record_name: &'a str,
+        txt_values: &'a [String],
+    ) -> DnsFuture<'a, DnsChangeInitialWait> {
+        Box::pin(async move {
+            self.update_txt_cloudflare(zone.zone_id(), record_name, txt_values)
+                .await?;
+            Ok(DnsChangeInitialWait::ConstTime(10))
+        })
+    }
+
+    fn delete_txt<'a>(
+        &'a self,
+        zone: &'a DnsZone,
+        record_name: &'a str,
+        txt_values: &'a [String],
+    ) -> DnsFuture<'a, ()> {
+        Box::pin(self.delete_txt_cloudflare(zone.zone_id(), record_name, txt_values))
+    }
+}
@@ -0,0 +1,117 @@
+//! External command hook, compatible with lego's exec provider
+use super::{DnsChangeInitialWait, DnsFuture, DnsProvider, DnsZone};
+use crate::Error;
+
+/// `[[exec]]` section in acme.toml
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct ExecConfig {
+    /// Program called as `<command> <args>... present|cleanup <fqdn> <value>`,
+    /// once per value as lego's exec provider does. `present` adds the value to the
+    /// TXT record and `cleanup` removes it, other values of the record must be kept.
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Environment variables of the command
+    #[serde(default)]
+    pub env: std::collections::HashMap<String, String>,
+    /// Domain suffixes handled by the command
+    pub zones: Vec<String>,
+    /// Kill the command after this seconds
+    #[serde(default = "ExecConfig::default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl ExecConfig {
+    fn default_timeout_secs() -> u64 {
+        60
+    }
+}
+
+pub struct ExecProvider {
+    config: ExecConfig,
+}
+
+impl ExecProvider {
+    pub fn new(config: &ExecConfig) -> Self {
+        Self {
+            config: config.clone(),
+        }
+    }
+
+    /// Run the command, fails on non-zero exit code or timeout
+    async fn run_hook(&self, mode: &str, record_name: &str, txt_value: &str) -> Result<(), Error> {
+        // FQDN with trailing dot, as lego does
+        let fqdn = format!("{}.", record_name.trim_end_matches('.'));
+        let mut command = tokio::process::Command::new(&self.config.command);
+        command
+            .args(&self.config.args)
+            .args([mode, fqdn.as_str(), txt_value])
+            .envs(&self.config.env)
+            .stdin(std::process::Stdio::null())
+            .kill_on_drop(true);
+
+        log::debug!("Run {} {} {}", self.config.command, mode, fqdn);
+        let timeout = std::time::Duration::from_secs(self.config.timeout_secs);
+        let output = tokio::time::timeout(timeout, command.output())
+            .await
+            .map_err(|_| {
+                Error::ExecHookFailed(format!("{} {} timed out", self.config.command, mode))
+            })??;
+
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(Error::ExecHookFailed(format!(
+                "{} {} {}: {}",
+                self.config.command,
+                mode,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )))
+        }
+    }
+}
+
+impl DnsProvider for ExecProvider {
+    fn name(&self) -> &str {
+        "Exec"
+    }
+
+    fn list_zones(&self) -> DnsFuture<'_, Vec<DnsZone>> {
+        let zones = self
+            .config
+            .zones
+            .iter()
+            .map(|zone| DnsZone::new(zone, zone))
+            .collect::<Vec<_>>();
+        Box::pin(async move { Ok(zones) })
+    }
+
+    fn upsert_txt<'a>(
+        &'a self,
+        _zone: &'a DnsZone,
+        record_name: &'a str,
+        txt_values: &'a [String],
+    ) -> DnsFuture<'a, DnsChangeInitialWait> {
+        Box::pin(async move {
+            for txt_value in txt_values {
+                self.run_hook("present", record_name, txt_value).await?;
+            }
+            Ok(DnsChangeInitialWait::ConstTime(0))
+        })
+    }
+
+    fn delete_txt<'a>(
+        &'a self,
+        _zone: &'a DnsZone,
+        record_name: &'a str,
+        txt_values: &'a [String],
+    ) -> DnsFuture<'a, ()> {
+        Box::pin(async move {
+            for txt_value in txt_values {
+                self.run_hook("cleanup", record_name, txt_value).await?;
+            }
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,74 @@
+//! JSON over HTTP client for DNS service APIs
+use crate::Error;
+
+pub(super) struct HttpClient(
+    hyper_util::client::legacy::Client<
+        hyper_rustls::HttpsConnector<hyper_util::client::legacy::connect::HttpConnector>,
+        http_body_util::Full<hyper::body::Bytes>,
+    >,
+);
+
+impl HttpClient {
+    pub(super) fn new() -> Result<Self, Error> {
+        let connector = hyper_rustls::HttpsConnectorBuilder::new()
+            .try_with_platform_verifier()
+            .map_err(|e| Error::DnsApiError(e.to_string()))?
+            .https_or_http()
+            .enable_http1()
+            .build();
+        Ok(Self(
+            hyper_util::client::legacy::Client::builder(hyper_util::rt::TokioExecutor::new())
+                .build(connector),
+        ))
+    }
+
+    /// Send request with JSON body, returns response body.
+    /// Fails if response status is not success.
+    pub(super) async fn request(
+        &self,
+        method: hyper::Method,
+        url: &url::Url,
+        headers: &[(&str, &str)],
+        body: Option<Vec<u8>>,
+    ) -> Result<hyper::body::Bytes, Error> {
+        use http_body_util::BodyExt;
+
+        let mut request = hyper::Request::builder()
+            .method(method)
+            .uri(url.as_str())
+            .header("Accept", "application/json");
+        if body.is_some() {
+            request = request.header("Content-Type", "application/json");
+        }
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let request = request
+            .body(http_body_util::Full::new(hyper::body::Bytes::from(
+                body.unwrap_or_default(),
+            )))
+            .map_err(|e| Error::DnsApiError(e.to_string()))?;
+
+        let response = self.0.request(request).await?;
+        let status = response.status();
+        let body = response.into_body().collect().await?.to_bytes();
+        if status.is_success() {
+            Ok(body)
+        } else {
+            Err(Error::DnsApiError(format!(
+                "{} returned {}: {}",
+                url,
+                status,
+                String::from_utf8_lossy(&body)
+            )))
+        }
+    }
+}
+
+/// Paths are relative to api_url, with or without trailing slash
+pub(super) fn api_base_url(api_url: &str) -> Result<url::Url, Error> {
+    Ok(url::Url::parse(&format!(
+        "{}/",
+        api_url.trim_end_matches('/')
+    ))?)
+}
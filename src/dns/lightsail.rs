@@ -0,0 +1,221 @@
+//! AWS Lightsail DNS zones
+use super::aws_retry::AwsRetry;
+use super::{DnsChangeInitialWait, DnsFuture, DnsProvider, DnsZone};
+use crate::Error;
+
+/// Lightsail has no published quota, keep it as low as Route53
+const REQUESTS_PER_SEC: u32 = 5;
+
+pub struct LightsailProvider {
+    client: aws_sdk_lightsail::Client,
+    retry: AwsRetry,
+}
+
+impl LightsailProvider {
+    pub fn new(client: aws_sdk_lightsail::Client) -> Self {
+        Self {
+            client,
+            retry: AwsRetry::new("Lightsail", REQUESTS_PER_SEC),
+        }
+    }
+
+    /// List all Lightsail DNS zones that AWS IAM role can access
+    async fn list_lightsail_zones(&self) -> Result<Vec<DnsZone>, Error> {
+        // Call Lightsail GetDomains API
+        let resp = self
+            .retry
+            .call("GetDomains", || self.client.get_domains().send())
+            .await?;
+        let domains = resp.domains();
+        // AWS SDK response -> DnsZone, Lightsail uses domain name as zone ID
+        let dns_zones = domains.iter().filter_map(|domain| {
+            domain.name().map(|name| {
+                // NS entries delegate subdomains to other name servers
+                let ns_names = domain
+                    .domain_entries()
+                    .iter()
+                    .filter(|entry| entry.r#type() == Some("NS"))
+                    .filter_map(|entry| entry.name());
+                DnsZone::new(name, name).with_delegations(ns_names)
+            })
+        });
+
+        Ok(dns_zones.collect::<Vec<_>>())
+    }
+
+    /// Current TXT entries whose name is record_name
+    async fn txt_entries(
+        &self,
+        domain_name: &str,
+        record_name: &str,
+    ) -> Result<Vec<aws_sdk_lightsail::types::DomainEntry>, Error> {
+        let resp = self
+            .retry
+            .call("GetDomain", || {
+                self.client.get_domain().domain_name(domain_name).send()
+            })
+            .await?;
+        let entries = resp
+            .domain()
+            .map(|domain| domain.domain_entries())
+            .unwrap_or_default()
+            .iter()
+            .filter(|entry| {
+                entry.r#type() == Some("TXT")
+                    && entry
+                        .name()
+                        .is_some_and(|name| name.eq_ignore_ascii_case(record_name))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        Ok(entries)
+    }
+
+    /// Lightsail holds one entry per TXT value.
+    /// Make entries of record_name to be exactly txt_values.
+    async fn update_txt_lightsail(
+        &self,
+        domain_name: &str,
+        record_name: &str,
+        txt_values: &[String],
+    ) -> Result<DnsChangeInitialWait, Error> {
+        let targets = txt_values
+            .iter()
+            .map(|txt_value| format!("\"{}\"", txt_value))
+            .collect::<Vec<_>>();
+
+        let existing = self.txt_entries(domain_name, record_name).await?;
+        // Values not published yet
+        let missing_targets = targets.iter().filter(|target| {
+            !existing
+                .iter()
+                .any(|entry| entry.target() == Some(target.as_str()))
+        });
+        // Entries that no longer needed, reused for missing values
+        let mut stale_entries = existing
+            .iter()
+            .filter(|entry| {
+                !entry
+                    .target()
+                    .is_some_and(|t| targets.iter().any(|v| v == t))
+            })
+            .collect::<Vec<_>>();
+
+        let mut created = false;
+        for target in missing_targets {
+            let entry = aws_sdk_lightsail::types::DomainEntry::builder()
+                .name(record_name)
+                .r#type("TXT")
+                .target(target);
+
+            if let Some(entry_id) = stale_entries.pop().and_then(|stale| stale.id()) {
+                // record_name entry already exists, update it.
+                let entry = entry.id(entry_id).build();
+                let _resp = self
+                    .retry
+                    .call("UpdateDomainEntry", || {
+                        self.client
+                            .update_domain_entry()
+                            .domain_name(domain_name)
+                            .domain_entry(entry.clone())
+                            .send()
+                    })
+                    .await?;
+            } else {
+                // No entry exists, create new one
+                let entry = entry.build();
+                let _resp = self
+                    .retry
+                    .call("CreateDomainEntry", || {
+                        self.client
+                            .create_domain_entry()
+                            .domain_name(domain_name)
+                            .domain_entry(entry.clone())
+                            .send()
+                    })
+                    .await?;
+                created = true;
+            }
+        }
+
+        // Remove remaining old values
+        for entry in stale_entries {
+            let _resp = self
+                .retry
+                .call("DeleteDomainEntry", || {
+                    self.client
+                        .delete_domain_entry()
+                        .domain_name(domain_name)
+                        .domain_entry(entry.clone())
+                        .send()
+                })
+                .await?;
+        }
+
+        if created {
+            // Lightsail DNS has long negatie cache TTL.
+            // To avoid NXDOMAIN caching, wait enough time after creation.
+            Ok(DnsChangeInitialWait::ConstTime(50))
+        } else {
+            Ok(DnsChangeInitialWait::ConstTime(10))
+        }
+    }
+
+    async fn delete_txt_lightsail(
+        &self,
+        domain_name: &str,
+        record_name: &str,
+        txt_values: &[String],
+    ) -> Result<(), Error> {
+        let targets = txt_values
+            .iter()
+            .map(|txt_value| format!("\"{}\"", txt_value))
+            .collect::<Vec<_>>();
+        let existing = self.txt_entries(domain_name, record_name).await?;
+        for entry in existing.into_iter().filter(|entry| {
+            entry
+                .target()
+                .is_some_and(|t| targets.iter().any(|v| v == t))
+        }) {
+            let _resp = self
+                .retry
+                .call("DeleteDomainEntry", || {
+                    self.client
+                        .delete_domain_entry()
+                        .domain_name(domain_name)
+                        .domain_entry(entry.clone())
+                        .send()
+                })
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+impl DnsProvider for LightsailProvider {
+    fn name(&self) -> &str {
+        "Lightsail"
+    }
+
+    fn list_zones(&self) -> DnsFuture<'_, Vec<DnsZone>> {
+        Box::pin(self.list_lightsail_zones())
+    }
+
+    fn upsert_txt<'a>(
+        &'a self,
+        zone: &'a DnsZone,
+        record_name: &'a str,
+        txt_values: &'a [String],
+    ) -> DnsFuture<'a, DnsChangeInitialWait> {
+        Box::pin(self.update_txt_lightsail(zone.zone_id(), record_name, txt_values))
+    }
+
+    fn delete_txt<'a>(
+        &'a self,
+        zone: &'a DnsZone,
+        record_name: &'a str,
+        txt_values: &'a [String],
+    ) -> DnsFuture<'a, ()> {
+        Box::pin(self.delete_txt_lightsail(zone.zone_id(), record_name, txt_values))
+    }
+}
@@ -0,0 +1,231 @@
+//! PowerDNS Authoritative HTTP API
+use super::http::{api_base_url, HttpClient};
+use super::{DnsChangeInitialWait, DnsFuture, DnsProvider, DnsZone};
+use crate::Error;
+
+/// `[[powerdns]]` section in acme.toml
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct PowerDnsConfig {
+    /// API endpoint, e.g. "http://127.0.0.1:8081"
+    pub api_url: String,
+    /// X-API-Key
+    pub api_key: String,
+    #[serde(default = "PowerDnsConfig::default_server_id")]
+    pub server_id: String,
+}
+
+impl PowerDnsConfig {
+    fn default_server_id() -> String {
+        "localhost".to_string()
+    }
+}
+
+pub struct PowerDnsProvider {
+    /// .../api/v1/servers/{server_id}/zones/
+    zones_url: url::Url,
+    api_key: String,
+    http_client: HttpClient,
+}
+
+#[derive(serde::Deserialize)]
+struct Zone {
+    id: String,
+    name: String,
+    #[serde(default)]
+    rrsets: Vec<RRSet>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+struct RRSet {
+    name: String,
+    #[serde(rename = "type")]
+    rr_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ttl: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    changetype: Option<String>,
+    #[serde(default)]
+    records: Vec<RRSetRecord>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+struct RRSetRecord {
+    content: String,
+    #[serde(default)]
+    disabled: bool,
+}
+
+impl PowerDnsProvider {
+    pub fn new(config: &PowerDnsConfig) -> Result<Self, Error> {
+        let mut zones_url = api_base_url(&config.api_url)?;
+        zones_url
+            .path_segments_mut()
+            .map_err(|_| Error::InvalidDnsProviderConfig(config.api_url.clone()))?
+            .pop_if_empty()
+            .extend(["api", "v1", "servers", &config.server_id, "zones", ""]);
+
+        Ok(Self {
+            zones_url,
+            api_key: config.api_key.clone(),
+            http_client: HttpClient::new()?,
+        })
+    }
+
+    fn zone_url(&self, zone_id: &str) -> url::Url {
+        let mut url = self.zones_url.clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty().push(zone_id);
+        }
+        url
+    }
+
+    async fn api_request(
+        &self,
+        method: hyper::Method,
+        url: &url::Url,
+        body: Option<Vec<u8>>,
+    ) -> Result<hyper::body::Bytes, Error> {
+        let headers = [("X-API-Key", self.api_key.as_str())];
+        self.http_client.request(method, url, &headers, body).await
+    }
+
+    async fn list_powerdns_zones(&self) -> Result<Vec<DnsZone>, Error> {
+        let response = self
+            .api_request(hyper::Method::GET, &self.zones_url, None)
+            .await?;
+        let zones = serde_json::from_slice::<Vec<Zone>>(&response)?;
+
+        // NS records of each zone, to find delegated subdomains
+        let dns_zones = futures::future::join_all(zones.iter().map(|zone| async move {
+            let dns_zone = DnsZone::new(&zone.name, &zone.id);
+            match self.list_ns_records(&zone.id).await {
+                Ok(ns_names) => dns_zone.with_delegations(ns_names),
+                Err(e) => {
+                    log::warn!("PowerDNS NS records of {}: {:?}", zone.name, e);
+                    dns_zone
+                }
+            }
+        }))
+        .await;
+
+        Ok(dns_zones)
+    }
+
+    async fn list_ns_records(&self, zone_id: &str) -> Result<Vec<String>, Error> {
+        let response = self
+            .api_request(hyper::Method::GET, &self.zone_url(zone_id), None)
+            .await?;
+        let zone = serde_json::from_slice::<Zone>(&response)?;
+        Ok(zone
+            .rrsets
+            .into_iter()
+            .filter(|rrset| rrset.rr_type == "NS")
+            .map(|rrset| rrset.name)
+            .collect())
+    }
+
+    /// Current values of the TXT RRset, without quotes
+    async fn txt_values_powerdns(
+        &self,
+        zone_id: &str,
+        record_name: &str,
+    ) -> Result<Vec<String>, Error> {
+        let rrset_name = format!("{}.", record_name.trim_end_matches('.'));
+        // Filtered by the server since PowerDNS 4.5, and by name here for older ones
+        let mut url = self.zone_url(zone_id);
+        url.query_pairs_mut()
+            .append_pair("rrset_name", &rrset_name)
+            .append_pair("rrset_type", "TXT");
+        let response = self.api_request(hyper::Method::GET, &url, None).await?;
+        let zone = serde_json::from_slice::<Zone>(&response)?;
+        Ok(zone
+            .rrsets
+            .into_iter()
+            .filter(|rrset| rrset.rr_type == "TXT" && rrset.name.eq_ignore_ascii_case(&rrset_name))
+            .flat_map(|rrset| rrset.records)
+            .map(|record| record.content.trim_matches('"').to_string())
+            .collect())
+    }
+
+    /// PATCH one TXT RRset, changetype is REPLACE or DELETE
+    async fn change_txt_powerdns(
+        &self,
+        changetype: &str,
+        zone_id: &str,
+        record_name: &str,
+        txt_values: &[String],
+    ) -> Result<(), Error> {
+        #[derive(serde::Serialize)]
+        struct Patch {
+            rrsets: Vec<RRSet>,
+        }
+
+        let rrset = RRSet {
+            name: format!("{}.", record_name.trim_end_matches('.')),
+            rr_type: "TXT".to_string(),
+            ttl: Some(60),
+            changetype: Some(changetype.to_string()),
+            records: txt_values
+                .iter()
+                .map(|txt_value| RRSetRecord {
+                    content: format!("\"{}\"", txt_value),
+                    disabled: false,
+                })
+                .collect(),
+        };
+        let body = serde_json::to_vec(&Patch {
+            rrsets: vec![rrset],
+        })?;
+
+        self.api_request(hyper::Method::PATCH, &self.zone_url(zone_id), Some(body))
+            .await?;
+        Ok(())
+    }
+}
+
+impl DnsProvider for PowerDnsProvider {
+    fn name(&self) -> &str {
+        "PowerDNS"
+    }
+
+    fn list_zones(&self) -> DnsFuture<'_, Vec<DnsZone>> {
+        Box::pin(self.list_powerdns_zones())
+    }
+
+    fn upsert_txt<'a>(
+        &'a self,
+        zone: &'a DnsZone,
+        record_name: &'a str,
+        txt_values: &'a [String],
+    ) -> DnsFuture<'a, DnsChangeInitialWait> {
+        Box::pin(async move {
+            self.change_txt_powerdns("REPLACE", zone.zone_id(), record_name, txt_values)
+                .await?;
+            Ok(DnsChangeInitialWait::ConstTime(0))
+        })
+    }
+
+    fn delete_txt<'a>(
+        &'a self,
+        zone: &'a DnsZone,
+        record_name: &'a str,
+        txt_values: &'a [String],
+    ) -> DnsFuture<'a, ()> {
+        Box::pin(async move {
+            // Other orders may still be validating their values in the same RRset
+            let remaining = self
+                .txt_values_powerdns(zone.zone_id(), record_name)
+                .await?
+                .into_iter()
+                .filter(|value| !txt_values.contains(value))
+                .collect::<Vec<_>>();
+            if remaining.is_empty() {
+                self.change_txt_powerdns("DELETE", zone.zone_id(), record_name, &[])
+                    .await
+            } else {
+                self.change_txt_powerdns("REPLACE", zone.zone_id(), record_name, &remaining)
+                    .await
+            }
+        })
+    }
+}
@@ -0,0 +1,296 @@
+//! Built-in authoritative DNS server for a delegated challenge zone
+use super::{is_subdomain_of, DnsChangeInitialWait, DnsFuture, DnsProvider, DnsZone};
+use crate::Error;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// `[dns_responder]` section in acme.toml
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct DnsResponderConfig {
+    /// Delegated zone, e.g. "acme.example.net"
+    pub zone: String,
+    /// UDP and TCP addresses to listen
+    #[serde(default = "DnsResponderConfig::default_listen")]
+    pub listen: Vec<std::net::SocketAddr>,
+    /// Host name of this server in NS record delegating the zone
+    pub nameserver: Option<String>,
+}
+
+impl DnsResponderConfig {
+    fn default_listen() -> Vec<std::net::SocketAddr> {
+        vec![std::net::SocketAddr::from(([0, 0, 0, 0], 53))]
+    }
+}
+
+/// TXT values by lowercase record name
+type TxtRecords = Arc<RwLock<HashMap<String, Vec<String>>>>;
+
+/// Serves TXT records of challenges being solved, while this is alive
+pub struct DnsResponder {
+    zone: String,
+    records: TxtRecords,
+    local_addrs: Vec<std::net::SocketAddr>,
+    // Listener tasks are aborted on drop, TCP connections with them
+    _tasks: tokio::task::JoinSet<()>,
+}
+
+impl DnsResponder {
+    /// Bind UDP and TCP sockets, then start serving
+    pub async fn bind(config: &DnsResponderConfig) -> Result<Self, Error> {
+        let zone = config.zone.trim_end_matches('.').to_ascii_lowercase();
+        let nameserver = config
+            .nameserver
+            .clone()
+            .unwrap_or_else(|| format!("ns.{}", zone));
+        let records = TxtRecords::default();
+        let answerer = Arc::new(Answerer {
+            zone: hickory_proto::rr::Name::from_ascii(format!("{}.", zone))?,
+            nameserver: hickory_proto::rr::Name::from_ascii(format!(
+                "{}.",
+                nameserver.trim_end_matches('.')
+            ))?,
+            records: records.clone(),
+        });
+
+        let mut tasks = tokio::task::JoinSet::new();
+        let mut local_addrs = Vec::new();
+        for addr in &config.listen {
+            let udp = tokio::net::UdpSocket::bind(addr).await?;
+            let tcp = tokio::net::TcpListener::bind(addr).await?;
+            log::info!("DNS responder for {} listening on {}", zone, addr);
+            local_addrs.push(udp.local_addr()?);
+            tasks.spawn(serve_udp(udp, answerer.clone()));
+            tasks.spawn(serve_tcp(tcp, answerer.clone()));
+        }
+
+        Ok(Self {
+            zone,
+            records,
+            local_addrs,
+            _tasks: tasks,
+        })
+    }
+
+    /// Bound UDP addresses, useful when the port is 0
+    pub fn local_addrs(&self) -> &[std::net::SocketAddr] {
+        &self.local_addrs
+    }
+}
+
+impl DnsProvider for DnsResponder {
+    fn name(&self) -> &str {
+        "Responder"
+    }
+
+    fn list_zones(&self) -> DnsFuture<'_, Vec<DnsZone>> {
+        let zones = vec![DnsZone::new(&self.zone, &self.zone)];
+        Box::pin(async move { Ok(zones) })
+    }
+
+    fn upsert_txt<'a>(
+        &'a self,
+        _zone: &'a DnsZone,
+        record_name: &'a str,
+        txt_values: &'a [String],
+    ) -> DnsFuture<'a, DnsChangeInitialWait> {
+        let record_name = record_name.trim_end_matches('.').to_ascii_lowercase();
+        if let Ok(mut records) = self.records.write() {
+            records.insert(record_name, txt_values.to_vec());
+        }
+        Box::pin(async { Ok(DnsChangeInitialWait::Published) })
+    }
+
+    fn delete_txt<'a>(
+        &'a self,
+        _zone: &'a DnsZone,
+        record_name: &'a str,
+        txt_values: &'a [String],
+    ) -> DnsFuture<'a, ()> {
+        let record_name = record_name.trim_end_matches('.').to_ascii_lowercase();
+        if let Ok(mut records) = self.records.write() {
+            if let Some(values) = records.get_mut(&record_name) {
+                values.retain(|value| !txt_values.contains(value));
+                if values.is_empty() {
+                    records.remove(&record_name);
+                }
+            }
+        }
+        Box::pin(async { Ok(()) })
+    }
+}
+
+struct Answerer {
+    zone: hickory_proto::rr::Name,
+    nameserver: hickory_proto::rr::Name,
+    records: TxtRecords,
+}
+
+impl Answerer {
+    /// Build response bytes for the request, None if not a DNS query
+    fn answer(&self, request: &[u8]) -> Option<Vec<u8>> {
+        use hickory_proto::op::{Message, MessageType, OpCode, ResponseCode};
+        use hickory_proto::rr::{rdata, RData, Record, RecordType};
+        use hickory_proto::serialize::binary::BinDecodable;
+
+        let request = Message::from_bytes(request).ok()?;
+        if request.message_type() != MessageType::Query {
+            return None;
+        }
+
+        let mut response = Message::new();
+        response
+            .set_id(request.id())
+            .set_message_type(MessageType::Response)
+            .set_op_code(request.op_code())
+            .set_recursion_desired(request.recursion_desired())
+            .add_queries(request.queries().iter().cloned());
+
+        let query = match (request.op_code(), request.queries()) {
+            (OpCode::Query, [query]) => query,
+            _ => {
+                response.set_response_code(ResponseCode::NotImp);
+                return response.to_vec().ok();
+            }
+        };
+
+        let name = query.name().to_ascii().to_ascii_lowercase();
+        let name = name.trim_end_matches('.');
+        let zone = self.zone.to_ascii();
+        let zone = zone.trim_end_matches('.');
+        if !is_subdomain_of(name, zone) {
+            response.set_response_code(ResponseCode::Refused);
+            return response.to_vec().ok();
+        }
+        response.set_authoritative(true);
+
+        let soa = Record::from_rdata(
+            self.zone.clone(),
+            0,
+            RData::SOA(rdata::SOA::new(
+                self.nameserver.clone(),
+                self.zone.prepend_label("hostmaster").ok()?,
+                crate::acme::unix_time_now() as u32,
+                3600,
+                600,
+                86400,
+                0,
+            )),
+        );
+
+        let txt_values = self
+            .records
+            .read()
+            .ok()
+            .and_then(|records| records.get(name).cloned());
+        match (query.query_type(), txt_values) {
+            (RecordType::TXT | RecordType::ANY, Some(txt_values)) => {
+                // TTL 0, values change while solving challenges
+                for txt_value in txt_values {
+                    let rdata = RData::TXT(rdata::TXT::new(vec![txt_value]));
+                    response.add_answer(Record::from_rdata(query.name().clone(), 0, rdata));
+                }
+            }
+            (RecordType::SOA, _) if name == zone => {
+                response.add_answer(soa);
+            }
+            (RecordType::NS, _) if name == zone => {
+                let rdata = RData::NS(rdata::NS(self.nameserver.clone()));
+                response.add_answer(Record::from_rdata(self.zone.clone(), 3600, rdata));
+            }
+            (_, txt_values) => {
+                // NODATA or NXDOMAIN
+                if txt_values.is_none() && name != zone {
+                    response.set_response_code(ResponseCode::NXDomain);
+                }
+                response.add_name_server(soa);
+            }
+        }
+
+        response.to_vec().ok()
+    }
+}
+
+async fn serve_udp(socket: tokio::net::UdpSocket, answerer: Arc<Answerer>) {
+    let mut buf = vec![0u8; 4096];
+    let mut backoff = Backoff::default();
+    loop {
+        match socket.recv_from(&mut buf).await {
+            Ok((len, peer)) => {
+                backoff.reset();
+                if let Some(response) = answerer.answer(&buf[..len]) {
+                    if let Err(e) = socket.send_to(&response, peer).await {
+                        log::debug!("DNS responder: send to {}: {}", peer, e);
+                    }
+                }
+            }
+            Err(e) => backoff.wait(e).await,
+        }
+    }
+}
+
+async fn serve_tcp(listener: tokio::net::TcpListener, answerer: Arc<Answerer>) {
+    // Connections are aborted when this task is aborted
+    let mut connections = tokio::task::JoinSet::new();
+    let mut backoff = Backoff::default();
+    loop {
+        // Forget finished connections
+        while connections.try_join_next().is_some() {}
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                backoff.reset();
+                connections.spawn(serve_tcp_connection(stream, answerer.clone()));
+            }
+            Err(e) => backoff.wait(e).await,
+        }
+    }
+}
+
+/// Delay after socket errors, so a persistent one does not spin the loop
+#[derive(Default)]
+struct Backoff {
+    errors: u32,
+}
+
+impl Backoff {
+    async fn wait(&mut self, e: std::io::Error) {
+        const MAX_DELAY_MS: u64 = 1000;
+
+        // e.g. ICMP port unreachable of an earlier response, retry at once
+        if matches!(
+            e.kind(),
+            std::io::ErrorKind::ConnectionReset | std::io::ErrorKind::Interrupted
+        ) {
+            log::debug!("DNS responder: {}", e);
+            return;
+        }
+        log::warn!("DNS responder: {}", e);
+        let delay_ms = (10u64 << self.errors.min(7)).min(MAX_DELAY_MS);
+        self.errors += 1;
+        tokio::time::sleep(std::time::Duration::from_millis(delay_ms)).await;
+    }
+
+    fn reset(&mut self) {
+        self.errors = 0;
+    }
+}
+
+/// DNS over TCP, RFC 1035 section 4.2.2, 2 bytes length prefix
+async fn serve_tcp_connection(mut stream: tokio::net::TcpStream, answerer: Arc<Answerer>) {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let timeout = std::time::Duration::from_secs(10);
+    while let Ok(Ok(len)) = tokio::time::timeout(timeout, stream.read_u16()).await {
+        let mut request = vec![0u8; len as usize];
+        if stream.read_exact(&mut request).await.is_err() {
+            break;
+        }
+        let Some(response) = answerer.answer(&request) else {
+            break;
+        };
+        if stream.write_u16(response.len() as u16).await.is_err()
+            || stream.write_all(&response).await.is_err()
+        {
+            break;
+        }
+    }
+}
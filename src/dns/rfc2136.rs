@@ -0,0 +1,225 @@
+//! RFC 2136 dynamic update, authenticated by TSIG (RFC 8945)
+use super::{DnsChangeInitialWait, DnsFuture, DnsProvider, DnsZone};
+use crate::Error;
+
+/// `[[rfc2136]]` section in acme.toml
+#[derive(Clone, Debug, serde::Deserialize)]
+pub struct Rfc2136Config {
+    /// Primary name server accepting updates, e.g. "192.0.2.53:53"
+    pub server: std::net::SocketAddr,
+    /// Zones served by the server
+    pub zones: Vec<String>,
+    /// TSIG key name
+    pub tsig_key_name: Option<String>,
+    /// "hmac-sha256" or "hmac-sha512"
+    #[serde(default)]
+    pub tsig_algorithm: TsigAlgorithm,
+    /// Base64 encoded TSIG secret
+    pub tsig_secret: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TsigAlgorithm {
+    #[default]
+    HmacSha256,
+    HmacSha512,
+}
+
+pub struct Rfc2136Provider {
+    server: std::net::SocketAddr,
+    zones: Vec<String>,
+    signer: Option<hickory_proto::dnssec::tsig::TSigner>,
+    timeout: std::time::Duration,
+}
+
+impl Rfc2136Provider {
+    pub fn new(config: &Rfc2136Config) -> Result<Self, Error> {
+        use base64::engine::{general_purpose::STANDARD, Engine};
+        use hickory_proto::dnssec::rdata::tsig::TsigAlgorithm as Algorithm;
+        use hickory_proto::dnssec::tsig::TSigner;
+
+        // TSIG is optional, e.g. the server accepts updates by IP address
+        let signer = match (&config.tsig_key_name, &config.tsig_secret) {
+            (Some(key_name), Some(secret)) => {
+                let invalid = |e: &dyn std::fmt::Display| {
+                    Error::InvalidDnsProviderConfig(format!("TSIG key {}: {}", key_name, e))
+                };
+                let secret = STANDARD.decode(secret).map_err(|e| invalid(&e))?;
+                let key_name = hickory_proto::rr::Name::from_ascii(key_name)?;
+                let algorithm = match config.tsig_algorithm {
+                    TsigAlgorithm::HmacSha256 => Algorithm::HmacSha256,
+                    TsigAlgorithm::HmacSha512 => Algorithm::HmacSha512,
+                };
+                // fudge: 300 seconds, recommended by RFC 8945
+                Some(TSigner::new(secret, algorithm, key_name, 300).map_err(|e| invalid(&e))?)
+            }
+            (None, None) => None,
+            _ => {
+                return Err(Error::InvalidDnsProviderConfig(
+                    "tsig_key_name and tsig_secret must be specified together".to_string(),
+                ))
+            }
+        };
+
+        Ok(Self {
+            server: config.server,
+            zones: config.zones.clone(),
+            signer,
+            timeout: std::time::Duration::from_secs(10),
+        })
+    }
+
+    /// Build UPDATE message.
+    /// Delete the whole TXT RRset if replace is true, then add or delete each values.
+    fn update_message(
+        zone_name: &str,
+        record_name: &str,
+        txt_values: &[String],
+        replace: bool,
+    ) -> Result<hickory_proto::op::Message, Error> {
+        use hickory_proto::op::{Message, MessageType, OpCode, Query, UpdateMessage};
+        use hickory_proto::rr::{rdata::TXT, DNSClass, Name, RData, Record, RecordType};
+
+        // names in config and challenge are always absolute
+        let mut zone = Name::from_ascii(zone_name)?;
+        zone.set_fqdn(true);
+        let mut name = Name::from_ascii(record_name)?;
+        name.set_fqdn(true);
+
+        // for updates, the query section is used for the zone
+        let mut zone_query = Query::query(zone, RecordType::SOA);
+        zone_query.set_query_class(DNSClass::IN);
+
+        let mut message = Message::new();
+        message
+            .set_id(rand_id())
+            .set_message_type(MessageType::Query)
+            .set_op_code(OpCode::Update)
+            .set_recursion_desired(false);
+        message.add_zone(zone_query);
+
+        if replace {
+            // RFC 2136 section 2.5.2, Delete An RRset
+            let mut delete_all = Record::update0(name.clone(), 0, RecordType::TXT);
+            delete_all.set_dns_class(DNSClass::ANY);
+            message.add_update(delete_all);
+        }
+
+        for txt_value in txt_values {
+            let rdata = RData::TXT(TXT::new(vec![txt_value.clone()]));
+            let mut record = Record::from_rdata(name.clone(), 60, rdata);
+            if replace {
+                // RFC 2136 section 2.5.1, Add To An RRset
+                record.set_dns_class(DNSClass::IN);
+            } else {
+                // RFC 2136 section 2.5.4, Delete An RR From An RRset
+                record.set_dns_class(DNSClass::NONE).set_ttl(0);
+            }
+            message.add_update(record);
+        }
+
+        Ok(message)
+    }
+
+    /// Sign and send the message over TCP, then check the response
+    async fn send_update(&self, mut message: hickory_proto::op::Message) -> Result<(), Error> {
+        use hickory_proto::op::{Message, ResponseCode};
+        use hickory_proto::serialize::binary::BinDecodable;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let verifier = if let Some(signer) = &self.signer {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs() as u32)
+                .unwrap_or_default();
+            message.finalize(signer, now)?
+        } else {
+            None
+        };
+        let request = message.to_vec()?;
+
+        let exchange = async {
+            // DNS over TCP, RFC 1035 section 4.2.2, 2 bytes length prefix
+            let mut stream = tokio::net::TcpStream::connect(self.server).await?;
+            stream.write_u16(request.len() as u16).await?;
+            stream.write_all(&request).await?;
+            let len = stream.read_u16().await?;
+            let mut response = vec![0u8; len as usize];
+            stream.read_exact(&mut response).await?;
+            Result::<Vec<u8>, Error>::Ok(response)
+        };
+        let response = tokio::time::timeout(self.timeout, exchange)
+            .await
+            .map_err(|_| Error::DnsUpdateTimeout)??;
+
+        // Error response may not be signed, e.g. BADKEY
+        let response_code = Message::from_bytes(&response)?.response_code();
+        if response_code != ResponseCode::NoError {
+            return Err(Error::DnsUpdateRefused(format!(
+                "{} returned {}",
+                self.server, response_code
+            )));
+        }
+
+        // Verify TSIG of the response
+        if let Some(mut verifier) = verifier {
+            verifier(&response)?;
+        }
+        Ok(())
+    }
+
+    async fn change_txt_rfc2136(
+        &self,
+        zone_name: &str,
+        record_name: &str,
+        txt_values: &[String],
+        replace: bool,
+    ) -> Result<(), Error> {
+        let message = Self::update_message(zone_name, record_name, txt_values, replace)?;
+        self.send_update(message).await
+    }
+}
+
+impl DnsProvider for Rfc2136Provider {
+    fn name(&self) -> &str {
+        "RFC2136"
+    }
+
+    fn list_zones(&self) -> DnsFuture<'_, Vec<DnsZone>> {
+        let zones = self
+            .zones
+            .iter()
+            .map(|zone| DnsZone::new(zone, zone))
+            .collect::<Vec<_>>();
+        Box::pin(async move { Ok(zones) })
+    }
+
+    fn upsert_txt<'a>(
+        &'a self,
+        zone: &'a DnsZone,
+        record_name: &'a str,
+        txt_values: &'a [String],
+    ) -> DnsFuture<'a, DnsChangeInitialWait> {
+        Box::pin(async move {
+            self.change_txt_rfc2136(zone.zone_id(), record_name, txt_values, true)
+                .await?;
+            // Primary server applies the update immediately, wait for secondaries
+            Ok(DnsChangeInitialWait::ConstTime(10))
+        })
+    }
+
+    fn delete_txt<'a>(
+        &'a self,
+        zone: &'a DnsZone,
+        record_name: &'a str,
+        txt_values: &'a [String],
+    ) -> DnsFuture<'a, ()> {
+        Box::pin(self.change_txt_rfc2136(zone.zone_id(), record_name, txt_values, false))
+    }
+}
+
+/// Random message ID
+fn rand_id() -> u16 {
+    fastrand::u16(..)
+}
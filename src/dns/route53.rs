@@ -0,0 +1,421 @@
+//! AWS Route53 hosted zones
+use super::aws_retry::AwsRetry;
+use super::{DnsChangeInitialWait, DnsFuture, DnsProvider, DnsZone};
+use crate::Error;
+use aws_sdk_route53::types::{Change, ChangeAction};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Changes arriving within this window are sent in one ChangeBatch
+const BATCH_WINDOW: std::time::Duration = std::time::Duration::from_millis(500);
+/// Changes in a ChangeBatch, enforced here. Route53 itself limits a ChangeBatch to
+/// 1000 ResourceRecord elements (UPSERT counts twice) and 32000 characters of values,
+/// 100 changes of challenge TXT records stay well below both.
+const MAX_BATCH_CHANGES: usize = 100;
+/// Route53 API quota is 5 requests per second per account
+const REQUESTS_PER_SEC: u32 = 5;
+
+pub struct Route53Provider {
+    client: aws_sdk_route53::Client,
+    retry: Arc<AwsRetry>,
+    pending: PendingChanges,
+    /// GetChange polling shared by the records of the same batch, true once INSYNC.
+    /// Removed when INSYNC, the provider lives across runs on Lambda warm starts.
+    change_status: Mutex<HashMap<String, Arc<futures::lock::Mutex<bool>>>>,
+}
+
+/// Changes waiting for the batch, by hosted zone id and action
+type PendingChanges = Arc<Mutex<HashMap<(String, ChangeAction), Vec<PendingChange>>>>;
+
+/// A change and the caller waiting for its ChangeInfo id
+struct PendingChange {
+    record_name: String,
+    change: Change,
+    sender: futures::channel::oneshot::Sender<Result<Option<String>, Error>>,
+}
+
+impl Route53Provider {
+    pub fn new(client: aws_sdk_route53::Client) -> Self {
+        Self {
+            client,
+            retry: Arc::new(AwsRetry::new("Route53", REQUESTS_PER_SEC)),
+            pending: Default::default(),
+            change_status: Default::default(),
+        }
+    }
+
+    /// List all Route53 hosted zones that AWS IAM role can access
+    async fn list_route53_zones(&self) -> Result<Vec<DnsZone>, Error> {
+        // Call Route53 ListHostedZones API
+        let pagenator = self
+            .retry
+            .call("ListHostedZones", || {
+                self.client
+                    .list_hosted_zones()
+                    .into_paginator()
+                    .send()
+                    .try_collect()
+            })
+            .await?;
+        // AWS SDK response -> DnsZone
+        let dns_zones = pagenator
+            .into_iter()
+            .flat_map(|page| page.hosted_zones)
+            // ListHostedZones returns zone name with '.' suffix, DnsZone::new() removes it.
+            // NS records are listed later by list_delegations(), only for zones in use.
+            .map(|zone| DnsZone::new(&zone.name, &zone.id));
+
+        Ok(dns_zones.collect::<Vec<_>>())
+    }
+
+    /// Names of all NS records in the hosted zone, reads all records of the zone
+    async fn list_ns_records(&self, hosted_zone_id: &str) -> Result<Vec<String>, Error> {
+        use aws_sdk_route53::types::RrType;
+
+        let mut ns_names = Vec::<String>::new();
+        let mut next_record = None::<(String, RrType)>;
+        loop {
+            // ListResourceRecordSets has no paginator, continue from the next record
+            let (start_name, start_type) = next_record.unzip();
+            let resp = self
+                .retry
+                .call("ListResourceRecordSets", || {
+                    self.client
+                        .list_resource_record_sets()
+                        .hosted_zone_id(hosted_zone_id)
+                        .set_start_record_name(start_name.clone())
+                        .set_start_record_type(start_type.clone())
+                        .send()
+                })
+                .await?;
+
+            ns_names.extend(
+                resp.resource_record_sets()
+                    .iter()
+                    .filter(|record| record.r#type() == &RrType::Ns)
+                    .map(|record| record.name().to_string()),
+            );
+
+            match (resp.next_record_name(), resp.next_record_type()) {
+                (Some(name), Some(rr_type)) if resp.is_truncated() => {
+                    next_record = Some((name.to_string(), rr_type.clone()));
+                }
+                _ => break,
+            }
+        }
+
+        Ok(ns_names)
+    }
+
+    async fn change_txt_route53(
+        &self,
+        action: ChangeAction,
+        hosted_zone_id: &str,
+        record_name: &str,
+        txt_values: &[String],
+    ) -> Result<DnsChangeInitialWait, Error> {
+        use aws_sdk_route53::types::{ResourceRecord, ResourceRecordSet, RrType};
+
+        // Multi-value RRset, e.g. for both example.com and *.example.com
+        let resource_records = txt_values
+            .iter()
+            .map(|txt_value| {
+                ResourceRecord::builder()
+                    .value(format!("\"{}\"", txt_value))
+                    .build()
+                    .unwrap() // unwrap() is safe when .value() was called
+            })
+            .collect::<Vec<_>>();
+
+        let record = ResourceRecordSet::builder()
+            .name(record_name)
+            .r#type(RrType::Txt)
+            .set_resource_records(Some(resource_records))
+            .ttl(60)
+            .build()
+            .unwrap(); // unwrap() is safe when .name() and .type() were called
+
+        let change = Change::builder()
+            .action(action.clone())
+            .resource_record_set(record)
+            .build()
+            .unwrap(); // unwrap() is safe when .action() was called
+
+        // Join the batch of the hosted zone, the first one sends it after the window
+        let (sender, receiver) = futures::channel::oneshot::channel();
+        let key = (hosted_zone_id.to_string(), action);
+        let first = {
+            let mut pending = self.pending.lock().unwrap();
+            let changes = pending.entry(key.clone()).or_default();
+            changes.push(PendingChange {
+                record_name: record_name.to_string(),
+                change,
+                sender,
+            });
+            changes.len() == 1
+        };
+        if first {
+            // Spawned, the batch is sent even if this caller is dropped
+            tokio::spawn(Self::send_batch(
+                self.client.clone(),
+                self.retry.clone(),
+                self.pending.clone(),
+                key,
+            ));
+        }
+
+        let change_id = receiver.await.map_err(|_| {
+            Error::DnsApiError(format!("Route53 change of {} was cancelled", record_name))
+        })??;
+        if let Some(change_id) = change_id {
+            Ok(DnsChangeInitialWait::ChangeId(change_id))
+        } else {
+            // Unexpected, wait 50 seconds before validate DNS records
+            Ok(DnsChangeInitialWait::ConstTime(50))
+        }
+    }
+
+    /// Send pending changes of the hosted zone in as few ChangeBatch as possible
+    async fn send_batch(
+        client: aws_sdk_route53::Client,
+        retry: Arc<AwsRetry>,
+        pending: PendingChanges,
+        key: (String, ChangeAction),
+    ) {
+        tokio::time::sleep(BATCH_WINDOW).await;
+        let mut changes = pending.lock().unwrap().remove(&key).unwrap_or_default();
+
+        while !changes.is_empty() {
+            // A ChangeBatch can not change the same record twice, leave it to the next batch
+            let mut batch = Vec::<PendingChange>::new();
+            let mut rest = Vec::new();
+            for change in changes {
+                if batch.len() < MAX_BATCH_CHANGES
+                    && batch.iter().all(|c| c.record_name != change.record_name)
+                {
+                    batch.push(change);
+                } else {
+                    rest.push(change);
+                }
+            }
+            changes = rest;
+
+            log::debug!(
+                "Route53 {} ChangeBatch of {} records in {}",
+                key.1,
+                batch.len(),
+                key.0
+            );
+            let result = Self::change_resource_record_sets(
+                &client,
+                &retry,
+                &key.0,
+                batch.iter().map(|c| c.change.clone()).collect(),
+            )
+            .await;
+
+            match result {
+                Ok(change_id) => {
+                    for change in batch {
+                        let _ = change.sender.send(Ok(change_id.clone()));
+                    }
+                }
+                Err(e) if batch.len() == 1 => {
+                    let _ = batch.pop().unwrap().sender.send(Err(e));
+                }
+                Err(e) if is_invalid_change_batch(&e) => {
+                    // One bad change must not fail others, send them one by one
+                    for change in batch {
+                        let result = Self::change_resource_record_sets(
+                            &client,
+                            &retry,
+                            &key.0,
+                            vec![change.change],
+                        )
+                        .await;
+                        let _ = change.sender.send(result);
+                    }
+                }
+                Err(e) => {
+                    let message = aws_sdk_route53::error::DisplayErrorContext(&e).to_string();
+                    for change in batch {
+                        let _ = change.sender.send(Err(Error::DnsApiError(message.clone())));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Call ChangeResourceRecordSets, returns ChangeInfo id
+    async fn change_resource_record_sets(
+        client: &aws_sdk_route53::Client,
+        retry: &AwsRetry,
+        hosted_zone_id: &str,
+        changes: Vec<Change>,
+    ) -> Result<Option<String>, Error> {
+        use aws_sdk_route53::types::ChangeBatch;
+
+        let change_batch = ChangeBatch::builder()
+            .set_changes(Some(changes))
+            .build()
+            .unwrap(); // unwrap() is safe when .changes() was set
+
+        let resp = retry
+            .call("ChangeResourceRecordSets", || {
+                client
+                    .change_resource_record_sets()
+                    .hosted_zone_id(hosted_zone_id)
+                    .change_batch(change_batch.clone())
+                    .send()
+            })
+            .await?;
+
+        Ok(resp.change_info().map(|info| info.id().to_string()))
+    }
+
+    /// TXT values answered by Route53 name servers of the hosted zone
+    async fn test_dns_answer_route53(
+        &self,
+        hosted_zone_id: &str,
+        record_name: &str,
+    ) -> Result<Vec<String>, Error> {
+        use aws_sdk_route53::types::RrType;
+
+        let answer = self
+            .retry
+            .call("TestDNSAnswer", || {
+                self.client
+                    .test_dns_answer()
+                    .hosted_zone_id(hosted_zone_id)
+                    .record_name(record_name)
+                    .record_type(RrType::Txt)
+                    .send()
+            })
+            .await?;
+        log::debug!(
+            "TestDNSAnswer {} on {}: {} {:?}",
+            record_name,
+            answer.nameserver(),
+            answer.response_code(),
+            answer.record_data()
+        );
+
+        Ok(answer
+            .record_data()
+            .iter()
+            .map(|value| value.trim_matches('"').to_string())
+            .collect())
+    }
+
+    /// Poll GetChange until the change becomes INSYNC
+    async fn wait_for_insync(
+        &self,
+        change_id: &str,
+        timeout: std::time::Duration,
+    ) -> Result<(), Error> {
+        use aws_sdk_route53::types::ChangeStatus;
+        const POLLING_INTERVAL_SECS: u64 = 10;
+
+        // Records of the same batch wait for one poller
+        let status = self
+            .change_status
+            .lock()
+            .unwrap()
+            .entry(change_id.to_string())
+            .or_default()
+            .clone();
+        let mut insync = status.lock().await;
+
+        let wait_start = std::time::Instant::now();
+        while !*insync && wait_start.elapsed() < timeout {
+            let resp = self
+                .retry
+                .call("GetChange", || {
+                    self.client.get_change().id(change_id).send()
+                })
+                .await?;
+            if let Some(change_info) = resp.change_info() {
+                if change_info.status() == &ChangeStatus::Insync {
+                    *insync = true;
+                    // Waiters holding the entry see true, later ones poll once again
+                    self.change_status.lock().unwrap().remove(change_id);
+                    break;
+                }
+            }
+            tokio::time::sleep(std::time::Duration::from_secs(POLLING_INTERVAL_SECS)).await;
+        }
+        Ok(())
+    }
+}
+
+impl DnsProvider for Route53Provider {
+    fn name(&self) -> &str {
+        "Route53"
+    }
+
+    fn list_zones(&self) -> DnsFuture<'_, Vec<DnsZone>> {
+        Box::pin(self.list_route53_zones())
+    }
+
+    fn list_delegations<'a>(&'a self, zone: &'a DnsZone) -> Option<DnsFuture<'a, Vec<String>>> {
+        Some(Box::pin(self.list_ns_records(zone.zone_id())))
+    }
+
+    fn upsert_txt<'a>(
+        &'a self,
+        zone: &'a DnsZone,
+        record_name: &'a str,
+        txt_values: &'a [String],
+    ) -> DnsFuture<'a, DnsChangeInitialWait> {
+        Box::pin(self.change_txt_route53(
+            ChangeAction::Upsert,
+            zone.zone_id(),
+            record_name,
+            txt_values,
+        ))
+    }
+
+    fn delete_txt<'a>(
+        &'a self,
+        zone: &'a DnsZone,
+        record_name: &'a str,
+        txt_values: &'a [String],
+    ) -> DnsFuture<'a, ()> {
+        Box::pin(async move {
+            self.change_txt_route53(
+                ChangeAction::Delete,
+                zone.zone_id(),
+                record_name,
+                txt_values,
+            )
+            .await?;
+            Ok(())
+        })
+    }
+
+    fn wait_for_change<'a>(
+        &'a self,
+        change_id: &'a str,
+        timeout: std::time::Duration,
+    ) -> DnsFuture<'a, ()> {
+        Box::pin(self.wait_for_insync(change_id, timeout))
+    }
+
+    fn test_dns_answer<'a>(
+        &'a self,
+        zone: &'a DnsZone,
+        record_name: &'a str,
+    ) -> Option<DnsFuture<'a, Vec<String>>> {
+        Some(Box::pin(
+            self.test_dns_answer_route53(zone.zone_id(), record_name),
+        ))
+    }
+}
+
+fn is_invalid_change_batch(e: &Error) -> bool {
+    matches!(
+        e,
+        Error::Route53ChangeRecordError(e)
+            if e.as_service_error().is_some_and(|e| e.is_invalid_change_batch())
+    )
+}
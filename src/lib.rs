@@ -1,18 +1,32 @@
 mod account;
 mod acme;
 mod aws_config;
+mod cname;
 mod config;
+mod crt;
 mod csr;
 mod dns;
 mod output;
+mod privkey;
+mod report;
 
 // re-exports
 pub use account::new_account;
-pub use acme::{AcmeIssuedCertificate, AcmeOrder, AcmeOrderBuilder};
+pub use acme::{AcmeIssuedCertificate, AcmeOrder, AcmeOrderBuilder, AuthorizationFailure};
+pub use cname::CnameMap;
 pub use config::{CertReqConfig, Config};
+pub use crt::X509Crt;
 pub use csr::X509Csr;
-pub use dns::{AllDnsZones, AwsClient, DnsZone};
-pub use output::write_crt;
+pub use dns::{
+    AcmeDnsAccount, AcmeDnsConfig, AcmeDnsProvider, AllDnsZones, AwsClient, CloudflareConfig,
+    CloudflareProvider, DnsChange, DnsChangeInitialWait, DnsCheckConfig, DnsFuture, DnsProtocol,
+    DnsProvider, DnsResponder, DnsResponderConfig, DnsZone, DnsZonePin, ExecConfig, ExecProvider,
+    LightsailProvider, PowerDnsConfig, PowerDnsProvider, Rfc2136Config, Rfc2136Provider,
+    Route53Provider, TsigAlgorithm,
+};
+pub use output::{read_crt, read_key, write_crt, write_key};
+pub use privkey::{KeyType, PrivateKey};
+pub use report::{CertificateReport, IssueOutcome, IssueReport};
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -30,50 +44,114 @@ pub enum Error {
     CsrParseError(#[from] x509_parser::nom::Err<x509_parser::error::X509Error>),
     #[error(transparent)]
     LightsailGetDomainsError(
-        #[from]
-        aws_sdk_lightsail::error::SdkError<
-            aws_sdk_lightsail::operation::get_domains::GetDomainsError,
+        Box<
+            aws_sdk_lightsail::error::SdkError<
+                aws_sdk_lightsail::operation::get_domains::GetDomainsError,
+            >,
+        >,
+    ),
+    #[error(transparent)]
+    LightsailGetDomainError(
+        Box<
+            aws_sdk_lightsail::error::SdkError<
+                aws_sdk_lightsail::operation::get_domain::GetDomainError,
+            >,
         >,
     ),
     #[error(transparent)]
     LightsailCreateEntryError(
-        #[from]
-        aws_sdk_lightsail::error::SdkError<
-            aws_sdk_lightsail::operation::create_domain_entry::CreateDomainEntryError,
+        Box<
+            aws_sdk_lightsail::error::SdkError<
+                aws_sdk_lightsail::operation::create_domain_entry::CreateDomainEntryError,
+            >,
         >,
     ),
     #[error(transparent)]
     LightsailUpdateEntryError(
-        #[from]
-        aws_sdk_lightsail::error::SdkError<
-            aws_sdk_lightsail::operation::update_domain_entry::UpdateDomainEntryError,
+        Box<
+            aws_sdk_lightsail::error::SdkError<
+                aws_sdk_lightsail::operation::update_domain_entry::UpdateDomainEntryError,
+            >,
+        >,
+    ),
+    #[error(transparent)]
+    LightsailDeleteEntryError(
+        Box<
+            aws_sdk_lightsail::error::SdkError<
+                aws_sdk_lightsail::operation::delete_domain_entry::DeleteDomainEntryError,
+            >,
         >,
     ),
     #[error(transparent)]
     Route53ListHostedZonesError(
-        #[from]
-        aws_sdk_route53::error::SdkError<
-            aws_sdk_route53::operation::list_hosted_zones::ListHostedZonesError,
+        Box<
+            aws_sdk_route53::error::SdkError<
+                aws_sdk_route53::operation::list_hosted_zones::ListHostedZonesError,
+            >,
+        >,
+    ),
+    #[error(transparent)]
+    Route53ListRecordsError(
+        Box<
+            aws_sdk_route53::error::SdkError<
+                aws_sdk_route53::operation::list_resource_record_sets::ListResourceRecordSetsError,
+            >,
         >,
     ),
     #[error(transparent)]
     Route53ChangeRecordError(
-        #[from]
-        aws_sdk_route53::error::SdkError<
-            aws_sdk_route53::operation::change_resource_record_sets::ChangeResourceRecordSetsError,
+        Box<
+            aws_sdk_route53::error::SdkError<
+                aws_sdk_route53::operation::change_resource_record_sets::ChangeResourceRecordSetsError,
+            >,
         >,
     ),
     #[error(transparent)]
     Route53GetChangeError(
-        #[from]
-        aws_sdk_route53::error::SdkError<aws_sdk_route53::operation::get_change::GetChangeError>,
+        Box<
+            aws_sdk_route53::error::SdkError<
+                aws_sdk_route53::operation::get_change::GetChangeError,
+            >,
+        >,
+    ),
+    #[error(transparent)]
+    Route53TestDnsAnswerError(
+        Box<
+            aws_sdk_route53::error::SdkError<
+                aws_sdk_route53::operation::test_dns_answer::TestDNSAnswerError,
+            >,
+        >,
     ),
     #[error(transparent)]
     S3PutObjectError(
-        #[from] aws_sdk_s3::error::SdkError<aws_sdk_s3::operation::put_object::PutObjectError>,
+        Box<
+            aws_sdk_s3::error::SdkError<
+                aws_sdk_s3::operation::put_object::PutObjectError,
+            >,
+        >,
     ),
     #[error(transparent)]
+    S3GetObjectError(
+        Box<
+            aws_sdk_s3::error::SdkError<
+                aws_sdk_s3::operation::get_object::GetObjectError,
+            >,
+        >,
+    ),
+    #[error(transparent)]
+    KeyGenerationError(#[from] rcgen::Error),
+    #[error(transparent)]
     DnsResolveError(#[from] hickory_resolver::ResolveError),
+    #[error(transparent)]
+    DnsProtoError(#[from] hickory_proto::ProtoError),
+    #[error(transparent)]
+    HttpError(#[from] hyper::Error),
+    #[error(transparent)]
+    HttpClientError(#[from] hyper_util::client::legacy::Error),
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+    #[error(transparent)]
+    UrlParseError(#[from] url::ParseError),
     #[error("Configuration file already exists")]
     ConfigExists,
     #[error("No DNS zone for {0}")]
@@ -82,57 +160,270 @@ pub enum Error {
     DnsChallengeNotSupported,
     #[error("Order must be DNS name")]
     InvalidAcmeOrder,
+    #[error("DNS01 challenge failed for {}", acme::join_failures(.0))]
+    AuthorizationFailed(Vec<AuthorizationFailure>),
     #[error("ACME challenge did not complete unexpectedly")]
     AcmeChallengeIncomplete,
     #[error("DNS update timeout")]
     DnsUpdateTimeout,
+    #[error("DNS hook command failed: {0}")]
+    ExecHookFailed(String),
+    #[error("DNS API error: {0}")]
+    DnsApiError(String),
+    #[error("DNS update refused: {0}")]
+    DnsUpdateRefused(String),
+    #[error("Invalid dns_check config: {0}")]
+    InvalidDnsCheckConfig(String),
+    #[error("Invalid DNS provider config: {0}")]
+    InvalidDnsProviderConfig(String),
+    #[error("Invalid cname entry: {0}")]
+    InvalidCname(String),
+    #[error("CNAME delegation mismatch: {0}")]
+    CnameMismatch(String),
     #[error("Certificate issue timeout")]
     CertificateIssueTimeout,
+    #[error("Certificate {0} not found")]
+    CertificateNotFound(String),
     #[error("Invalid out_crt_file")]
     InvalidOutCrtFile(String),
+    #[error("Invalid out_key_file")]
+    InvalidOutKeyFile(String),
+    #[error("Invalid certificate request: {0}")]
+    InvalidCertRequest(String),
 }
 
-pub async fn issue_certificates(config: &Config) -> Result<(), Error> {
+/// AWS SDK errors are large, boxed to keep Result<_, Error> small.
+/// From impls let `?` convert them as #[from] does.
+macro_rules! impl_from_boxed {
+    ($($variant:ident($error:ty),)*) => {
+        $(
+            impl From<$error> for Error {
+                fn from(e: $error) -> Self {
+                    Self::$variant(Box::new(e))
+                }
+            }
+        )*
+    };
+}
+
+impl_from_boxed! {
+    LightsailGetDomainsError(
+        aws_sdk_lightsail::error::SdkError<
+            aws_sdk_lightsail::operation::get_domains::GetDomainsError,
+        >
+    ),
+    LightsailGetDomainError(
+        aws_sdk_lightsail::error::SdkError<
+            aws_sdk_lightsail::operation::get_domain::GetDomainError,
+        >
+    ),
+    LightsailCreateEntryError(
+        aws_sdk_lightsail::error::SdkError<
+            aws_sdk_lightsail::operation::create_domain_entry::CreateDomainEntryError,
+        >
+    ),
+    LightsailUpdateEntryError(
+        aws_sdk_lightsail::error::SdkError<
+            aws_sdk_lightsail::operation::update_domain_entry::UpdateDomainEntryError,
+        >
+    ),
+    LightsailDeleteEntryError(
+        aws_sdk_lightsail::error::SdkError<
+            aws_sdk_lightsail::operation::delete_domain_entry::DeleteDomainEntryError,
+        >
+    ),
+    Route53ListHostedZonesError(
+        aws_sdk_route53::error::SdkError<
+            aws_sdk_route53::operation::list_hosted_zones::ListHostedZonesError,
+        >
+    ),
+    Route53ListRecordsError(
+        aws_sdk_route53::error::SdkError<
+            aws_sdk_route53::operation::list_resource_record_sets::ListResourceRecordSetsError,
+        >
+    ),
+    Route53ChangeRecordError(
+        aws_sdk_route53::error::SdkError<
+            aws_sdk_route53::operation::change_resource_record_sets::ChangeResourceRecordSetsError,
+        >
+    ),
+    Route53GetChangeError(
+        aws_sdk_route53::error::SdkError<
+            aws_sdk_route53::operation::get_change::GetChangeError,
+        >
+    ),
+    Route53TestDnsAnswerError(
+        aws_sdk_route53::error::SdkError<
+            aws_sdk_route53::operation::test_dns_answer::TestDNSAnswerError,
+        >
+    ),
+    S3PutObjectError(
+        aws_sdk_s3::error::SdkError<
+            aws_sdk_s3::operation::put_object::PutObjectError,
+        >
+    ),
+    S3GetObjectError(
+        aws_sdk_s3::error::SdkError<
+            aws_sdk_s3::operation::get_object::GetObjectError,
+        >
+    ),
+}
+
+/// Issue all certificates in config.
+/// A failure of one certificate does not stop others, see the returned report.
+pub async fn issue_certificates(config: &Config) -> Result<IssueReport, Error> {
     use futures::StreamExt;
     const REQUEST_CONCURRENT: usize = 4;
 
+    let start = std::time::Instant::now();
+
     // Default region config for S3 put
     let aws_sdk_config = aws_config::aws_config_from_env(None).await;
 
-    // Load DNS records that current AWS credential can manage
+    // Load DNS records that current AWS credential can manage,
+    // and zones of DNS servers in config
     let aws_client = AwsClient::new().await;
-    let zones = AllDnsZones::load(&aws_client).await?;
+    let mut providers = AllDnsZones::aws_providers(&aws_client);
+    providers.extend(config.dns_providers().await?);
+    let zones = AllDnsZones::load_with_providers(providers)
+        .await?
+        .with_dns_check(config.dns_check().clone());
 
     let fut = config.certificate_requests().map(|crt_req| async {
-        // Load & check request
-        let crt_req = crt_req.clone();
-        let order = AcmeOrder::new(config, &crt_req)?.load_and_check_csr(&zones)?;
+        let crt_start = std::time::Instant::now();
+        let result = issue_certificate(config, crt_req, &zones, &aws_sdk_config).await;
 
-        // Request certificate to ACME server
-        let certificate = order.request_certificate(&zones).await?;
+        let (outcome, crt) = match result {
+            Ok((outcome, crt)) => (outcome, crt),
+            Err(e) => {
+                log::error!("{}: {}", crt_req.crt_file_name(), e);
+                let error = e.to_string();
+                (IssueOutcome::Failed { error }, None)
+            }
+        };
+        CertificateReport {
+            crt_file: crt_req.crt_file_name().to_string(),
+            outcome,
+            elapsed_secs: crt_start.elapsed().as_secs_f64(),
+            serial: crt.as_ref().map(|crt| crt.serial_hex()),
+            not_after: crt.as_ref().map(|crt| crt.not_after_string().to_string()),
+        }
+    });
+
+    // Request certificates concurrently, keep the order of config
+    let stream = futures::stream::iter(fut).buffered(REQUEST_CONCURRENT);
+    let certificates = stream.collect::<Vec<_>>().await;
+
+    Ok(IssueReport {
+        certificates,
+        elapsed_secs: start.elapsed().as_secs_f64(),
+    })
+}
+
+/// Revoke the certificate in local file or S3, signed by the account key
+pub async fn revoke_certificate(
+    config: &Config,
+    crt_file_name: &str,
+    reason: Option<instant_acme::RevocationReason>,
+) -> Result<X509Crt, Error> {
+    // Default region config for S3 get
+    let aws_sdk_config = aws_config::aws_config_from_env(None).await;
+
+    let crt_pem = read_crt(crt_file_name, &aws_sdk_config)
+        .await?
+        .ok_or_else(|| Error::CertificateNotFound(crt_file_name.to_string()))?;
+    let crt = X509Crt::from_pem(&crt_pem)?;
+
+    log::info!("Revoke certificate serial {}", crt.serial_hex());
+    let crt_der = rustls_pki_types::CertificateDer::from(crt.der_bytes());
+    config
+        .account()
+        .revoke(&instant_acme::RevocationRequest {
+            certificate: &crt_der,
+            reason,
+        })
+        .await?;
 
-        // Save server certificate
-        let server_crt_pem = certificate.server_certificate_pem();
-        write_crt(crt_req.crt_file_name(), server_crt_pem, &aws_sdk_config).await?;
+    Ok(crt)
+}
+
+/// Issue one certificate, returns the issued one or the current one if skipped
+async fn issue_certificate(
+    config: &Config,
+    crt_req: &CertReqConfig,
+    zones: &AllDnsZones,
+    aws_sdk_config: &::aws_config::SdkConfig,
+) -> Result<(IssueOutcome, Option<X509Crt>), Error> {
+    let mut order_builder = AcmeOrder::new(config, crt_req)?;
 
-        if let Some(iss_file_name) = crt_req.iss_file_name() {
-            // Save issuer certificate (CA intermidiate)
-            let issuer_crt_pem = certificate.issuer_certificate_pem();
-            write_crt(iss_file_name, issuer_crt_pem, &aws_sdk_config).await?;
+    // Skip if the current certificate is not near expiry, before any DNS work
+    if let Some(renew_before_days) = crt_req.renew_before_days() {
+        if let Some(crt_pem) = read_crt(crt_req.crt_file_name(), aws_sdk_config).await? {
+            let current = X509Crt::from_pem(&crt_pem)?;
+            if !order_builder
+                .needs_renewal(&current, renew_before_days)
+                .await?
+            {
+                log::info!("Skip {}, not in renewal window", crt_req.crt_file_name());
+                return Ok((IssueOutcome::Skipped, Some(current)));
+            }
+            order_builder = order_builder.replaces(&current);
         }
+    }
 
-        Result::<(), Error>::Ok(())
-    });
+    // Load & check request
+    if crt_req.csr_file_name().is_none() {
+        // Generate CSR from domains
+        let private_key = load_or_generate_key(crt_req, aws_sdk_config).await?;
+        order_builder = order_builder.private_key(private_key);
+    }
+    let order = order_builder.load_and_check_csr(zones).await?;
+
+    // Request certificate to ACME server
+    let certificate = order.request_certificate(zones).await?;
 
-    // Request certificates concurrently
-    let stream = futures::stream::iter(fut).buffer_unordered(REQUEST_CONCURRENT);
-    let results = stream.collect::<Vec<_>>().await;
+    // Save private key of generated CSR
+    if let (Some(key_file_name), Some(key_pem)) = (crt_req.key_file_name(), order.private_key_pem())
+    {
+        write_key(key_file_name, key_pem, aws_sdk_config).await?;
+    }
+
+    // Save server certificate
+    let server_crt_pem = certificate.server_certificate_pem();
+    write_crt(crt_req.crt_file_name(), server_crt_pem, aws_sdk_config).await?;
 
-    if let Some(e) = results.into_iter().find_map(|result| result.err()) {
-        // Returns first error
-        Err(e)
-    } else {
-        // no error
-        Ok(())
+    if let Some(iss_file_name) = crt_req.iss_file_name() {
+        // Save issuer certificate (CA intermidiate)
+        let issuer_crt_pem = certificate.issuer_certificate_pem();
+        write_crt(iss_file_name, issuer_crt_pem, aws_sdk_config).await?;
     }
+
+    Ok((
+        IssueOutcome::Issued,
+        Some(certificate.server_certificate()?),
+    ))
+}
+
+/// Reuse the existing private key if configured, otherwise generate new one
+async fn load_or_generate_key(
+    crt_req: &CertReqConfig,
+    aws_sdk_config: &::aws_config::SdkConfig,
+) -> Result<PrivateKey, Error> {
+    if let (true, Some(key_file_name)) = (crt_req.reuse_key(), crt_req.key_file_name()) {
+        if let Some(key_pem) = read_key(key_file_name, aws_sdk_config).await? {
+            let private_key = PrivateKey::from_pem(&String::from_utf8_lossy(&key_pem))?;
+            if private_key.key_type() == Some(crt_req.key_type()) {
+                log::debug!("Reuse private key {}", key_file_name);
+                return Ok(private_key);
+            }
+            log::info!(
+                "Private key {} is not {:?}, generate new one",
+                key_file_name,
+                crt_req.key_type()
+            );
+        }
+    }
+
+    log::debug!("Generate {:?} private key", crt_req.key_type());
+    PrivateKey::generate(crt_req.key_type())
 }
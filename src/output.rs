@@ -8,7 +8,14 @@ pub async fn write_crt(
     if let Ok(url) = url::Url::parse(crt_file_name) {
         if url.scheme() == "s3" {
             log::debug!("Write certificate to S3 {}", crt_file_name);
-            write_crt_s3(&url, certificate_pem.into_bytes(), aws_sdk_config).await
+            let content_type = "application/pem-certificate-chain";
+            write_s3(
+                &url,
+                certificate_pem.into_bytes(),
+                content_type,
+                aws_sdk_config,
+            )
+            .await
         } else {
             Err(Error::InvalidOutCrtFile(crt_file_name.to_string()))
         }
@@ -18,6 +25,32 @@ pub async fn write_crt(
     }
 }
 
+/// Write private key, local file is readable only by owner
+pub async fn write_key(
+    key_file_name: &str,
+    private_key_pem: String,
+    aws_sdk_config: &aws_config::SdkConfig,
+) -> Result<(), Error> {
+    if let Ok(url) = url::Url::parse(key_file_name) {
+        if url.scheme() == "s3" {
+            log::debug!("Write private key to S3 {}", key_file_name);
+            let content_type = "application/pkcs8";
+            write_s3(
+                &url,
+                private_key_pem.into_bytes(),
+                content_type,
+                aws_sdk_config,
+            )
+            .await
+        } else {
+            Err(Error::InvalidOutKeyFile(key_file_name.to_string()))
+        }
+    } else {
+        log::debug!("Write private key to local file {}", key_file_name);
+        write_key_localfile(key_file_name, private_key_pem.as_bytes())
+    }
+}
+
 fn write_crt_localfile(crt_file_name: &str, crt_bytes: &[u8]) -> Result<(), Error> {
     use std::io::Write;
     let mut f = std::fs::File::create(crt_file_name)?;
@@ -25,15 +58,33 @@ fn write_crt_localfile(crt_file_name: &str, crt_bytes: &[u8]) -> Result<(), Erro
     Ok(())
 }
 
-async fn write_crt_s3(
-    crt_file_url: &url::Url,
-    crt_bytes: Vec<u8>,
+fn write_key_localfile(key_file_name: &str, key_bytes: &[u8]) -> Result<(), Error> {
+    use std::io::Write;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    {
+        // owner rw only, from creation so the key is never readable by others
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut f = options.open(key_file_name)?;
+    f.write_all(key_bytes)?;
+    Ok(())
+}
+
+async fn write_s3(
+    file_url: &url::Url,
+    bytes: Vec<u8>,
+    content_type: &str,
     aws_sdk_config: &aws_config::SdkConfig,
 ) -> Result<(), Error> {
-    let bucket = crt_file_url
+    let bucket = file_url
         .host_str()
-        .ok_or_else(|| Error::InvalidOutCrtFile(crt_file_url.to_string()))?;
-    let key = crt_file_url.path().trim_start_matches('/');
+        .ok_or_else(|| Error::InvalidOutCrtFile(file_url.to_string()))?;
+    let key = file_url.path().trim_start_matches('/');
 
     let client = aws_sdk_s3::Client::new(aws_sdk_config);
 
@@ -41,10 +92,76 @@ async fn write_crt_s3(
         .put_object()
         .bucket(bucket)
         .key(key)
-        .content_type("application/pem-certificate-chain")
-        .body(crt_bytes.into())
+        .content_type(content_type)
+        .body(bytes.into())
         .send()
         .await?;
 
     Ok(())
 }
+
+/// Read current certificate, returns None if it does not exist yet
+pub async fn read_crt(
+    crt_file_name: &str,
+    aws_sdk_config: &aws_config::SdkConfig,
+) -> Result<Option<Vec<u8>>, Error> {
+    if let Ok(url) = url::Url::parse(crt_file_name) {
+        if url.scheme() == "s3" {
+            log::debug!("Read certificate from S3 {}", crt_file_name);
+            read_s3(&url, aws_sdk_config).await
+        } else {
+            Err(Error::InvalidOutCrtFile(crt_file_name.to_string()))
+        }
+    } else {
+        log::debug!("Read certificate from local file {}", crt_file_name);
+        read_localfile(crt_file_name)
+    }
+}
+
+/// Read current private key, returns None if it does not exist yet
+pub async fn read_key(
+    key_file_name: &str,
+    aws_sdk_config: &aws_config::SdkConfig,
+) -> Result<Option<Vec<u8>>, Error> {
+    if let Ok(url) = url::Url::parse(key_file_name) {
+        if url.scheme() == "s3" {
+            log::debug!("Read private key from S3 {}", key_file_name);
+            read_s3(&url, aws_sdk_config).await
+        } else {
+            Err(Error::InvalidOutKeyFile(key_file_name.to_string()))
+        }
+    } else {
+        log::debug!("Read private key from local file {}", key_file_name);
+        read_localfile(key_file_name)
+    }
+}
+
+fn read_localfile(file_name: &str) -> Result<Option<Vec<u8>>, Error> {
+    match std::fs::read(file_name) {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn read_s3(
+    file_url: &url::Url,
+    aws_sdk_config: &aws_config::SdkConfig,
+) -> Result<Option<Vec<u8>>, Error> {
+    let bucket = file_url
+        .host_str()
+        .ok_or_else(|| Error::InvalidOutCrtFile(file_url.to_string()))?;
+    let key = file_url.path().trim_start_matches('/');
+
+    let client = aws_sdk_s3::Client::new(aws_sdk_config);
+
+    let resp = client.get_object().bucket(bucket).key(key).send().await;
+    match resp {
+        Ok(resp) => {
+            let bytes = resp.body.collect().await.map_err(std::io::Error::other)?;
+            Ok(Some(bytes.to_vec()))
+        }
+        Err(e) if e.as_service_error().is_some_and(|e| e.is_no_such_key()) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,86 @@
+//! Private key of the server certificate, generated instead of reading CSR file
+use crate::Error;
+
+/// Private key algorithm
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeyType {
+    #[default]
+    EcdsaP256,
+    EcdsaP384,
+    Rsa2048,
+    Rsa3072,
+    Rsa4096,
+}
+
+pub struct PrivateKey {
+    key_pair: rcgen::KeyPair,
+}
+
+impl PrivateKey {
+    /// Generate new random key
+    pub fn generate(key_type: KeyType) -> Result<Self, Error> {
+        use rcgen::{KeyPair, RsaKeySize, PKCS_RSA_SHA256};
+
+        let key_pair = match key_type {
+            KeyType::EcdsaP256 => KeyPair::generate_for(&rcgen::PKCS_ECDSA_P256_SHA256)?,
+            KeyType::EcdsaP384 => KeyPair::generate_for(&rcgen::PKCS_ECDSA_P384_SHA384)?,
+            KeyType::Rsa2048 => KeyPair::generate_rsa_for(&PKCS_RSA_SHA256, RsaKeySize::_2048)?,
+            KeyType::Rsa3072 => KeyPair::generate_rsa_for(&PKCS_RSA_SHA256, RsaKeySize::_3072)?,
+            KeyType::Rsa4096 => KeyPair::generate_rsa_for(&PKCS_RSA_SHA256, RsaKeySize::_4096)?,
+        };
+
+        Ok(Self { key_pair })
+    }
+
+    /// Read PEM encoded PKCS#8 key
+    pub fn from_pem(key_pem: &str) -> Result<Self, Error> {
+        let key_pair = rcgen::KeyPair::from_pem(key_pem)?;
+        Ok(Self { key_pair })
+    }
+
+    /// Algorithm of this key, None if it is not one of KeyType
+    pub fn key_type(&self) -> Option<KeyType> {
+        use rcgen::PublicKeyData;
+        use x509_parser::{prelude::FromDer, public_key::PublicKey, x509::SubjectPublicKeyInfo};
+
+        let algorithm = self.key_pair.algorithm();
+        if algorithm == &rcgen::PKCS_ECDSA_P256_SHA256 {
+            return Some(KeyType::EcdsaP256);
+        }
+        if algorithm == &rcgen::PKCS_ECDSA_P384_SHA384 {
+            return Some(KeyType::EcdsaP384);
+        }
+
+        // RSA key size from the public key
+        let public_key_der = self.key_pair.subject_public_key_info();
+        let (_rem, spki) = SubjectPublicKeyInfo::from_der(&public_key_der).ok()?;
+        match spki.parsed().ok()? {
+            PublicKey::RSA(rsa) => match rsa.key_size() {
+                2048 => Some(KeyType::Rsa2048),
+                3072 => Some(KeyType::Rsa3072),
+                4096 => Some(KeyType::Rsa4096),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// PEM encoded PKCS#8 key
+    pub fn to_pem(&self) -> String {
+        self.key_pair.serialize_pem()
+    }
+
+    /// Create DER encoded CSR, the first domain becomes subject common name
+    pub fn create_csr_der(&self, domains: &[String]) -> Result<Vec<u8>, Error> {
+        let mut params = rcgen::CertificateParams::new(domains)?;
+        let mut subject = rcgen::DistinguishedName::new();
+        if let Some(common_name) = domains.first() {
+            subject.push(rcgen::DnType::CommonName, common_name.as_str());
+        }
+        params.distinguished_name = subject;
+
+        let csr = params.serialize_request(&self.key_pair)?;
+        Ok(csr.der().to_vec())
+    }
+}
@@ -0,0 +1,63 @@
+//! Per-certificate results of issue_certificates()
+
+/// Results of all certificate requests, in configuration order
+#[derive(Debug, serde::Serialize)]
+pub struct IssueReport {
+    pub certificates: Vec<CertificateReport>,
+    pub elapsed_secs: f64,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct CertificateReport {
+    /// out_crt_file of the request
+    pub crt_file: String,
+    #[serde(flatten)]
+    pub outcome: IssueOutcome,
+    pub elapsed_secs: f64,
+    /// Serial number of the issued certificate, or the current one if skipped
+    pub serial: Option<String>,
+    /// Expiration of the issued certificate, or the current one if skipped
+    pub not_after: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum IssueOutcome {
+    Issued,
+    Skipped,
+    Failed { error: String },
+}
+
+impl IssueReport {
+    /// True if any certificate request failed
+    pub fn has_failure(&self) -> bool {
+        self.certificates
+            .iter()
+            .any(|crt| matches!(crt.outcome, IssueOutcome::Failed { .. }))
+    }
+}
+
+impl std::fmt::Display for IssueReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for crt in &self.certificates {
+            match &crt.outcome {
+                IssueOutcome::Issued => write!(f, "issued  {}", crt.crt_file)?,
+                IssueOutcome::Skipped => write!(f, "skipped {}", crt.crt_file)?,
+                IssueOutcome::Failed { error } => write!(f, "failed  {}: {}", crt.crt_file, error)?,
+            }
+            if let Some(serial) = &crt.serial {
+                write!(f, ", serial {}", serial)?;
+            }
+            if let Some(not_after) = &crt.not_after {
+                write!(f, ", expires {}", not_after)?;
+            }
+            writeln!(f, " ({:.1}s)", crt.elapsed_secs)?;
+        }
+        write!(
+            f,
+            "{} certificate requests in {:.1}s",
+            self.certificates.len(),
+            self.elapsed_secs
+        )
+    }
+}
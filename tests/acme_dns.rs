@@ -0,0 +1,44 @@
+use acme_client_route53::{AcmeDnsAccount, AcmeDnsConfig, AcmeDnsProvider, DnsProvider};
+mod common;
+
+#[tokio::test]
+async fn acme_dns_update_test() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let api_url = format!("http://{}", listener.local_addr().unwrap());
+    let server = tokio::spawn(common::serve_http(
+        listener,
+        vec![r#"{"txt":"token-1"}"#, r#"{"txt":"token-2"}"#],
+    ));
+
+    let account = AcmeDnsAccount {
+        username: "user-1".to_string(),
+        password: "secret-1".to_string(),
+        subdomain: "d420c923".to_string(),
+        fulldomain: "d420c923.auth.example.org".to_string(),
+    };
+    let provider = AcmeDnsProvider::new(&AcmeDnsConfig {
+        api_url,
+        accounts: [("www.example.com".to_string(), account)].into(),
+    })
+    .unwrap();
+
+    let zones = provider.list_zones().await.unwrap();
+    assert_eq!("d420c923.auth.example.org", zones[0].domain_name());
+
+    // one update request per value
+    let txt_values = vec!["token-1".to_string(), "token-2".to_string()];
+    provider
+        .upsert_txt(&zones[0], "d420c923.auth.example.org", &txt_values)
+        .await
+        .unwrap();
+
+    let requests = server.await.unwrap();
+    for (request, txt_value) in requests.iter().zip(&txt_values) {
+        assert_eq!("POST /update", request.method_path);
+        assert!(request.head.contains("x-api-user: user-1"));
+        assert!(request.head.contains("x-api-key: secret-1"));
+        let body = serde_json::from_str::<serde_json::Value>(&request.body).unwrap();
+        assert_eq!("d420c923", body["subdomain"]);
+        assert_eq!(txt_value.as_str(), body["txt"]);
+    }
+}
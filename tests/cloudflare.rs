@@ -0,0 +1,58 @@
+use acme_client_route53::{CloudflareConfig, CloudflareProvider, DnsProvider};
+
+mod common;
+
+#[tokio::test]
+async fn cloudflare_test() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let api_url = format!("http://{}/client/v4", listener.local_addr().unwrap());
+    let server = tokio::spawn(common::serve_http(
+        listener,
+        vec![
+            r#"{"success": true, "result": [{"id": "zone-1", "name": "example.com"}],
+                "result_info": {"page": 1, "total_pages": 1}}"#,
+            r#"{"success": true, "result": [
+                {"id": "record-1", "content": "\"stale\""},
+                {"id": "record-2", "content": "\"token-1\""}]}"#,
+            r#"{"success": true, "result": {"id": "record-3"}}"#,
+            r#"{"success": true, "result": {"id": "record-1"}}"#,
+        ],
+    ));
+
+    let provider = CloudflareProvider::new(&CloudflareConfig {
+        api_token: "token-secret".to_string(),
+        api_url,
+    })
+    .unwrap();
+
+    let zones = provider.list_zones().await.unwrap();
+    assert_eq!("example.com", zones[0].domain_name());
+    assert_eq!("zone-1", zones[0].zone_id());
+
+    // token-1 exists, create token-2 and delete stale one
+    let txt_values = vec!["token-1".to_string(), "token-2".to_string()];
+    provider
+        .upsert_txt(&zones[0], "_acme-challenge.www.example.com", &txt_values)
+        .await
+        .unwrap();
+
+    let requests = server.await.unwrap();
+    assert!(requests[0].method_path.starts_with("GET /client/v4/zones?"));
+    assert!(requests[0]
+        .head
+        .contains("authorization: bearer token-secret"));
+    assert_eq!(
+        "GET /client/v4/zones/zone-1/dns_records?type=TXT&name=_acme-challenge.www.example.com&per_page=100",
+        requests[1].method_path
+    );
+    assert_eq!(
+        "POST /client/v4/zones/zone-1/dns_records",
+        requests[2].method_path
+    );
+    let created = serde_json::from_str::<serde_json::Value>(&requests[2].body).unwrap();
+    assert_eq!("\"token-2\"", created["content"]);
+    assert_eq!(
+        "DELETE /client/v4/zones/zone-1/dns_records/record-1",
+        requests[3].method_path
+    );
+}
@@ -0,0 +1,233 @@
+use acme_client_route53::CnameMap;
+
+mod common;
+
+#[test]
+fn cname_pattern_test() {
+    let cname = CnameMap::try_from(std::collections::HashMap::from([
+        (
+            "_acme-challenge.*.customers.example.com".to_string(),
+            "{1}.acme.example.net".to_string(),
+        ),
+        (
+            "_acme-challenge.*.*.example.com".to_string(),
+            "{2}-{1}.acme.example.net".to_string(),
+        ),
+        (
+            "_acme-challenge.vip.customers.example.com".to_string(),
+            "vip.example.org".to_string(),
+        ),
+    ]))
+    .unwrap();
+
+    assert_eq!(
+        cname.get("_acme-challenge.shop.customers.example.com"),
+        Some("shop.acme.example.net".to_string())
+    );
+    assert_eq!(
+        cname.get("_acme-challenge.Shop.Customers.example.com."),
+        Some("shop.acme.example.net".to_string())
+    );
+    // Exact entry first
+    assert_eq!(
+        cname.get("_acme-challenge.vip.customers.example.com"),
+        Some("vip.example.org".to_string())
+    );
+    // Less specific pattern
+    assert_eq!(
+        cname.get("_acme-challenge.www.staff.example.com"),
+        Some("staff-www.acme.example.net".to_string())
+    );
+    // A wildcard matches one label only
+    assert_eq!(cname.get("_acme-challenge.a.b.customers.example.com"), None);
+    assert_eq!(cname.get("_acme-challenge.example.com"), None);
+}
+
+#[test]
+fn cname_pattern_tie_test() {
+    // Equally specific, HashMap order differs on each build
+    for _ in 0..16 {
+        let cname = CnameMap::try_from(std::collections::HashMap::from([
+            (
+                "_acme-challenge.x.*.com".to_string(),
+                "{1}.right.example.net".to_string(),
+            ),
+            (
+                "_acme-challenge.*.a.com".to_string(),
+                "{1}.left.example.net".to_string(),
+            ),
+        ]))
+        .unwrap();
+        assert_eq!(
+            cname.get("_acme-challenge.x.a.com"),
+            Some("x.left.example.net".to_string())
+        );
+    }
+}
+
+#[test]
+fn cname_placeholder_test() {
+    // Error of loading the entry
+    let cname_error = |name: &str, target: &str| {
+        CnameMap::try_from(std::collections::HashMap::from([(
+            name.to_string(),
+            target.to_string(),
+        )]))
+        .err()
+    };
+
+    assert!(cname_error("_acme-challenge.*.*.example.com", "{1}-{2}.example.net").is_none());
+    assert!(matches!(
+        cname_error("_acme-challenge.*.example.com", "{2}.example.net"),
+        Some(acme_client_route53::Error::InvalidCname(_))
+    ));
+    assert!(cname_error("_acme-challenge.*.example.com", "{0}.example.net").is_some());
+    assert!(cname_error("_acme-challenge.www.example.com", "{1}.example.net").is_some());
+}
+
+/// Order of the first certificate request, checked against zones of a DNS responder
+async fn check_order(
+    config: &acme_client_route53::Config,
+) -> Result<(), acme_client_route53::Error> {
+    use acme_client_route53::{AcmeOrder, AllDnsZones, DnsResponder, DnsResponderConfig};
+
+    let responder = DnsResponder::bind(&DnsResponderConfig {
+        zone: "acme.example.net".to_string(),
+        listen: vec!["127.0.0.1:0".parse().unwrap()],
+        nameserver: None,
+    })
+    .await
+    .unwrap();
+    let zones = AllDnsZones::load_with_providers(vec![Box::new(responder)])
+        .await
+        .unwrap();
+    let crt_req = config.certificate_requests().next().unwrap();
+    let private_key = acme_client_route53::PrivateKey::generate(crt_req.key_type()).unwrap();
+    AcmeOrder::new(config, crt_req)?
+        .private_key(private_key)
+        .load_and_check_csr(&zones)
+        .await
+        .map(|_order| ())
+}
+
+#[tokio::test]
+async fn verify_cname_test() {
+    let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let nameserver = socket.local_addr().unwrap();
+    let server = tokio::spawn(common::serve_cname(
+        socket,
+        vec![
+            ("_acme-challenge.www.example.com", "www.acme.example.net"),
+            ("_acme-challenge.api.example.com", "old.acme.example.net"),
+        ],
+    ));
+
+    let config_toml = |options: &str, label: &str| {
+        format!(
+            r#"
+{options}
+[cname]
+"_acme-challenge.{label}.example.com" = "{label}.acme.example.net"
+[dns_check]
+nameservers = ["{nameserver}"]
+[[certificate_requests]]
+domains = ["{label}.example.com"]
+out_key_file = "key.pem"
+out_crt_file = "crt.pem"
+"#
+        )
+    };
+
+    // Published CNAME points at the configured target
+    let config = common::offline_config(&config_toml("", "www")).await;
+    check_order(&config).await.unwrap();
+
+    // Points at another name, checked by default
+    let config = common::offline_config(&config_toml("", "api")).await;
+    assert!(matches!(
+        check_order(&config).await,
+        Err(acme_client_route53::Error::CnameMismatch(_))
+    ));
+
+    // Opted out, e.g. for split-horizon DNS
+    let config = common::offline_config(&config_toml("verify_cname = false", "api")).await;
+    check_order(&config).await.unwrap();
+
+    server.abort();
+}
+
+#[tokio::test]
+async fn wildcard_challenge_record_test() {
+    // example.com and *.example.com share _acme-challenge.example.com
+    let config = common::offline_config(
+        r#"
+verify_cname = false
+[cname]
+"_acme-challenge.example.com" = "example.acme.example.net"
+[[certificate_requests]]
+domains = ["example.com", "*.example.com"]
+out_key_file = "key.pem"
+out_crt_file = "crt.pem"
+"#,
+    )
+    .await;
+    check_order(&config).await.unwrap();
+
+    // The shared record cannot be in two zones
+    let config = common::offline_config(
+        r#"
+verify_cname = false
+[cname]
+"_acme-challenge.example.com" = "example.acme.example.net"
+[[certificate_requests]]
+domains = ["example.com", "*.example.com"]
+out_key_file = "key.pem"
+out_crt_file = "crt.pem"
+[certificate_requests.hostnames]
+"example.com" = { dns_provider = "Responder" }
+"*.example.com" = { dns_provider = "Route53" }
+"#,
+    )
+    .await;
+    assert!(matches!(
+        check_order(&config).await,
+        Err(acme_client_route53::Error::InvalidCertRequest(_))
+    ));
+}
+
+#[tokio::test]
+async fn discover_cname_test() {
+    let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let nameserver = socket.local_addr().unwrap();
+    let server = tokio::spawn(common::serve_cname(
+        socket,
+        vec![("_acme-challenge.www.example.com", "www.acme.example.net")],
+    ));
+
+    let config_toml = |label: &str| {
+        format!(
+            r#"
+discover_cname = true
+[dns_check]
+nameservers = ["{nameserver}"]
+[[certificate_requests]]
+domains = ["{label}.example.com"]
+out_key_file = "key.pem"
+out_crt_file = "crt.pem"
+"#
+        )
+    };
+
+    // Delegated to the responder zone by CNAME on DNS
+    let config = common::offline_config(&config_toml("www")).await;
+    check_order(&config).await.unwrap();
+
+    // No CNAME, the record stays in example.com which has no zone
+    let config = common::offline_config(&config_toml("api")).await;
+    assert!(matches!(
+        check_order(&config).await,
+        Err(acme_client_route53::Error::NoDnsZone(_))
+    ));
+
+    server.abort();
+}
@@ -0,0 +1,137 @@
+// Each test crate uses a part of the helpers
+#![allow(dead_code)]
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// Request received by [`serve_http`]
+pub struct HttpRequest {
+    /// e.g. "POST /update"
+    pub method_path: String,
+    /// Request line and headers, lowercase
+    pub head: String,
+    pub body: String,
+}
+
+/// In-process HTTP server, answers each request with the next JSON body
+pub async fn serve_http(
+    listener: tokio::net::TcpListener,
+    response_bodies: Vec<&'static str>,
+) -> Vec<HttpRequest> {
+    let responses = response_bodies
+        .into_iter()
+        .map(|body| (200, body))
+        .collect();
+    serve_http_with_status(listener, responses).await
+}
+
+/// Same as [`serve_http`], with HTTP status code of each response
+pub async fn serve_http_with_status(
+    listener: tokio::net::TcpListener,
+    responses: Vec<(u16, &'static str)>,
+) -> Vec<HttpRequest> {
+    let mut received = Vec::new();
+    for (status, response_body) in responses {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut buf = Vec::new();
+        let mut chunk = [0u8; 1024];
+        // read until the whole body of Content-Length arrives
+        let (head, body) = loop {
+            let n = stream.read(&mut chunk).await.unwrap();
+            buf.extend_from_slice(&chunk[..n]);
+            let text = String::from_utf8_lossy(&buf).to_string();
+            if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                let head = head.to_ascii_lowercase();
+                let content_length = head
+                    .lines()
+                    .find_map(|line| line.strip_prefix("content-length: "))
+                    .map(|len| len.parse::<usize>().unwrap())
+                    .unwrap_or(0);
+                if content_length <= body.len() {
+                    break (head, body.to_string());
+                }
+            }
+        };
+        let response = format!(
+            "HTTP/1.1 {} Status\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            response_body.len(),
+            response_body
+        );
+        stream.write_all(response.as_bytes()).await.unwrap();
+
+        let request_line = String::from_utf8_lossy(&buf)
+            .lines()
+            .next()
+            .unwrap()
+            .to_string();
+        let method_path = request_line.rsplit_once(' ').unwrap().0.to_string();
+        received.push(HttpRequest {
+            method_path,
+            head,
+            body,
+        });
+    }
+    received
+}
+
+/// Config loaded without network access, ACME server URLs are given instead of directory
+pub async fn offline_config(config_toml: &str) -> acme_client_route53::Config {
+    use base64::prelude::{Engine, BASE64_URL_SAFE_NO_PAD};
+
+    let key = rcgen::KeyPair::generate().unwrap();
+    let account_toml = format!(
+        r#"
+[account]
+id = "https://acme.invalid/acct/1"
+key_pkcs8 = "{}"
+[account.urls]
+newNonce = "https://acme.invalid/new-nonce"
+newAccount = "https://acme.invalid/new-acct"
+newOrder = "https://acme.invalid/new-order"
+"#,
+        BASE64_URL_SAFE_NO_PAD.encode(key.serialize_der())
+    );
+    acme_client_route53::Config::from_str(&format!("{}\n{}", config_toml, account_toml))
+        .await
+        .unwrap()
+}
+
+/// In-process DNS server answering CNAME records, NXDOMAIN for other names
+pub async fn serve_cname(socket: tokio::net::UdpSocket, cnames: Vec<(&'static str, &'static str)>) {
+    use hickory_proto::op::{Message, MessageType, ResponseCode};
+    use hickory_proto::rr::{rdata, Name, RData, Record};
+    use hickory_proto::serialize::binary::BinDecodable;
+
+    let mut buf = vec![0u8; 4096];
+    loop {
+        let (len, peer) = socket.recv_from(&mut buf).await.unwrap();
+        let request = Message::from_bytes(&buf[..len]).unwrap();
+        let mut response = Message::new();
+        response
+            .set_id(request.id())
+            .set_message_type(MessageType::Response)
+            .set_recursion_desired(request.recursion_desired())
+            .set_recursion_available(true)
+            .add_queries(request.queries().iter().cloned());
+
+        let query_name = request.queries()[0].name().clone();
+        let name = query_name.to_ascii().to_ascii_lowercase();
+        match cnames
+            .iter()
+            .find(|(cname, _)| name.trim_end_matches('.') == *cname)
+        {
+            Some((_, target)) => {
+                let target = Name::from_ascii(format!("{}.", target)).unwrap();
+                let rdata = RData::CNAME(rdata::CNAME(target));
+                response.add_answer(Record::from_rdata(query_name, 0, rdata));
+            }
+            None => {
+                response.set_response_code(ResponseCode::NXDomain);
+            }
+        }
+        socket
+            .send_to(&response.to_vec().unwrap(), peer)
+            .await
+            .unwrap();
+    }
+}
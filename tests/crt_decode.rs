@@ -0,0 +1,71 @@
+mod common;
+
+#[test]
+fn crt_decode_test() {
+    let crt_pem = std::fs::read("tests/example.crt").unwrap();
+    let crt = acme_client_route53::X509Crt::from_pem(&crt_pem).unwrap();
+    let subjects = crt.subjects().collect::<Vec<_>>();
+
+    assert_eq!(
+        subjects.as_slice(),
+        [
+            "www.example.com",
+            "www.example.com",
+            "alt1.example.com",
+            "alt2.example.com",
+        ]
+    );
+    // 2025-04-01T00:00:00Z
+    assert_eq!(crt.not_after(), 1743465600);
+    assert!(crt.days_to_expiration() < 0);
+    assert_eq!(crt.serial_hex(), "01:23:45:67:89:ab:cd:ef");
+
+    let cert_id = crt.certificate_identifier().unwrap();
+    assert_eq!(
+        cert_id.to_string(),
+        "k5-bvO3fh-ENX-NA81WCZbCAmLk.ASNFZ4mrze8"
+    );
+}
+
+#[tokio::test]
+async fn needs_renewal_test() {
+    use acme_client_route53::{AcmeOrder, X509Crt};
+
+    // Valid until 4096, issued for lowercase names
+    let key = rcgen::KeyPair::generate().unwrap();
+    let mut params = rcgen::CertificateParams::new(vec![
+        "www.example.com".to_string(),
+        "api.example.com".to_string(),
+    ])
+    .unwrap();
+    params.distinguished_name = rcgen::DistinguishedName::new();
+    let crt_pem = params.self_signed(&key).unwrap().pem();
+    let current = X509Crt::from_pem(crt_pem.as_bytes()).unwrap();
+
+    let config_toml = |domains: &str| {
+        format!(
+            r#"
+[[certificate_requests]]
+domains = {domains}
+out_key_file = "key.pem"
+out_crt_file = "crt.pem"
+"#
+        )
+    };
+
+    // Case and trailing dot of domains in config do not matter
+    let config =
+        common::offline_config(&config_toml(r#"["WWW.Example.com.", "api.example.com"]"#)).await;
+    let crt_req = config.certificate_requests().next().unwrap();
+    let order = AcmeOrder::new(&config, crt_req).unwrap();
+    assert!(!order.needs_renewal(&current, 30).await.unwrap());
+
+    // Added hostname
+    let config = common::offline_config(&config_toml(
+        r#"["www.example.com", "api.example.com", "new.example.com"]"#,
+    ))
+    .await;
+    let crt_req = config.certificate_requests().next().unwrap();
+    let order = AcmeOrder::new(&config, crt_req).unwrap();
+    assert!(order.needs_renewal(&current, 30).await.unwrap());
+}
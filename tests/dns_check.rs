@@ -0,0 +1,197 @@
+use acme_client_route53::{DnsCheckConfig, DnsProtocol, Error};
+
+fn validate(protocol: DnsProtocol, nameserver: &str, tls_dns_name: Option<&str>) -> bool {
+    let config = DnsCheckConfig {
+        nameservers: vec![nameserver.to_string()],
+        protocol,
+        tls_dns_name: tls_dns_name.map(str::to_string),
+        ..Default::default()
+    };
+    match config.validate() {
+        Ok(()) => true,
+        Err(Error::InvalidDnsCheckConfig(_)) => false,
+        Err(e) => panic!("unexpected error: {}", e),
+    }
+}
+
+#[test]
+fn tls_dns_name_test() {
+    assert!(validate(
+        DnsProtocol::Tls,
+        "192.0.2.53",
+        Some("dns.example.net")
+    ));
+    assert!(validate(
+        DnsProtocol::Https,
+        "192.0.2.53",
+        Some("dns.example.net")
+    ));
+    assert!(!validate(DnsProtocol::Tls, "192.0.2.53", None));
+    assert!(!validate(DnsProtocol::Https, "192.0.2.53", None));
+    assert!(validate(DnsProtocol::Udp, "192.0.2.53", None));
+
+    // System resolvers do not speak tls
+    let config = DnsCheckConfig {
+        protocol: DnsProtocol::Tls,
+        tls_dns_name: Some("dns.example.net".to_string()),
+        ..Default::default()
+    };
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn nameserver_address_test() {
+    for nameserver in [
+        "192.0.2.53",
+        "192.0.2.53:5353",
+        "2001:db8::53",
+        "[2001:db8::53]:5353",
+    ] {
+        assert!(
+            validate(DnsProtocol::Udp, nameserver, None),
+            "{}",
+            nameserver
+        );
+    }
+    assert!(!validate(DnsProtocol::Udp, "dns.example.net", None));
+    assert!(!validate(DnsProtocol::Udp, "dns.example.net:53", None));
+}
+
+/// DNS over TLS resolver answering TXT of one name, records every query
+async fn serve_dot(
+    listener: tokio::net::TcpListener,
+    acceptor: tokio_rustls::TlsAcceptor,
+    txt: (&'static str, &'static str),
+    queries: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+) {
+    use hickory_proto::op::{Message, MessageType, ResponseCode};
+    use hickory_proto::rr::{rdata, RData, Record, RecordType};
+    use hickory_proto::serialize::binary::BinDecodable;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    loop {
+        let (stream, _) = listener.accept().await.unwrap();
+        let Ok(mut stream) = acceptor.accept(stream).await else {
+            continue;
+        };
+        while let Ok(len) = stream.read_u16().await {
+            let mut buf = vec![0u8; len as usize];
+            stream.read_exact(&mut buf).await.unwrap();
+            let request = Message::from_bytes(&buf).unwrap();
+            let query = request.queries()[0].clone();
+            let name = query.name().to_ascii().to_ascii_lowercase();
+            queries
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", query.query_type(), name));
+
+            let mut response = Message::new();
+            response
+                .set_id(request.id())
+                .set_message_type(MessageType::Response)
+                .set_recursion_desired(request.recursion_desired())
+                .set_recursion_available(true)
+                .add_query(query.clone());
+            if query.query_type() == RecordType::TXT && name == format!("{}.", txt.0) {
+                let rdata = RData::TXT(rdata::TXT::new(vec![txt.1.to_string()]));
+                response.add_answer(Record::from_rdata(query.name().clone(), 0, rdata));
+            } else {
+                response.set_response_code(ResponseCode::NXDomain);
+            }
+            let response = response.to_vec().unwrap();
+            stream.write_u16(response.len() as u16).await.unwrap();
+            stream.write_all(&response).await.unwrap();
+        }
+    }
+}
+
+/// TLS acceptor for dns.test, and PEM of the CA certificate signing it
+fn tls_acceptor() -> (tokio_rustls::TlsAcceptor, String) {
+    use rcgen::{BasicConstraints, CertificateParams, IsCa, Issuer, KeyPair};
+    use tokio_rustls::rustls;
+
+    let ca_key = KeyPair::generate().unwrap();
+    let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca_cert = ca_params.self_signed(&ca_key).unwrap();
+    let issuer = Issuer::new(ca_params, ca_key);
+
+    let server_key = KeyPair::generate().unwrap();
+    let server_cert = CertificateParams::new(vec!["dns.test".to_string()])
+        .unwrap()
+        .signed_by(&server_key, &issuer)
+        .unwrap();
+
+    let server_config = rustls::ServerConfig::builder_with_provider(std::sync::Arc::new(
+        rustls::crypto::aws_lc_rs::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .unwrap()
+    .with_no_client_auth()
+    .with_single_cert(
+        vec![server_cert.der().clone()],
+        rustls::pki_types::PrivateKeyDer::try_from(server_key.serialize_der()).unwrap(),
+    )
+    .unwrap();
+    (
+        tokio_rustls::TlsAcceptor::from(std::sync::Arc::new(server_config)),
+        ca_cert.pem(),
+    )
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn tls_propagation_test() {
+    use acme_client_route53::{AllDnsZones, ExecConfig, ExecProvider};
+
+    // Trusted by the platform verifier of the resolver
+    let (acceptor, ca_pem) = tls_acceptor();
+    let ca_file = std::env::temp_dir().join(format!("dns_check_ca_{}.pem", std::process::id()));
+    std::fs::write(&ca_file, ca_pem).unwrap();
+    std::env::set_var("SSL_CERT_FILE", &ca_file);
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let resolver_addr = listener.local_addr().unwrap();
+    let queries = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let record_name = "_acme-challenge.www.example.com";
+    let server = tokio::spawn(serve_dot(
+        listener,
+        acceptor,
+        (record_name, "token-1"),
+        queries.clone(),
+    ));
+
+    // Provider publishes nothing, the DNS over TLS resolver answers
+    let provider = ExecProvider::new(&ExecConfig {
+        command: "true".to_string(),
+        args: Vec::new(),
+        env: Default::default(),
+        zones: vec!["example.com".to_string()],
+        timeout_secs: 5,
+    });
+    let zones = AllDnsZones::load_with_providers(vec![Box::new(provider)])
+        .await
+        .unwrap()
+        .with_dns_check(DnsCheckConfig {
+            nameservers: vec![resolver_addr.to_string()],
+            protocol: DnsProtocol::Tls,
+            tls_dns_name: Some("dns.test".to_string()),
+            timeout_secs: Some(5),
+            ..Default::default()
+        });
+    let txt_values = vec!["token-1".to_string()];
+    let dns_change = zones
+        .update_txt_record(record_name, &txt_values)
+        .await
+        .unwrap();
+    assert!(dns_change.wait_for_propergation(30).await.unwrap());
+
+    // Only TXT through the resolver, no NS lookup to ask authoritative servers on port 53
+    assert_eq!(
+        vec![format!("TXT {}.", record_name)],
+        *queries.lock().unwrap()
+    );
+
+    server.abort();
+    std::fs::remove_file(&ca_file).unwrap();
+}
@@ -0,0 +1,266 @@
+use acme_client_route53::{DnsChangeInitialWait, DnsFuture, DnsProvider, DnsZone};
+
+/// In-memory DNS provider
+struct StaticZones(Vec<&'static str>);
+
+impl DnsProvider for StaticZones {
+    fn name(&self) -> &str {
+        "static"
+    }
+
+    fn list_zones(&self) -> DnsFuture<'_, Vec<DnsZone>> {
+        let zones = self.0.iter().map(|name| DnsZone::new(name, name)).collect();
+        Box::pin(async move { Ok(zones) })
+    }
+
+    fn upsert_txt<'a>(
+        &'a self,
+        _zone: &'a DnsZone,
+        _record_name: &'a str,
+        _txt_values: &'a [String],
+    ) -> DnsFuture<'a, DnsChangeInitialWait> {
+        Box::pin(async { Ok(DnsChangeInitialWait::ConstTime(0)) })
+    }
+
+    fn delete_txt<'a>(
+        &'a self,
+        _zone: &'a DnsZone,
+        _record_name: &'a str,
+        _txt_values: &'a [String],
+    ) -> DnsFuture<'a, ()> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn test_dns_answer<'a>(
+        &'a self,
+        _zone: &'a DnsZone,
+        _record_name: &'a str,
+    ) -> Option<DnsFuture<'a, Vec<String>>> {
+        Some(Box::pin(async { Ok(vec!["token-1".to_string()]) }))
+    }
+}
+
+#[tokio::test]
+async fn find_zone_test() {
+    let zones = acme_client_route53::AllDnsZones::load_with_providers(vec![
+        Box::new(StaticZones(vec!["example.com."])),
+        Box::new(StaticZones(vec!["sub.example.com", "example.net"])),
+    ])
+    .await
+    .unwrap();
+
+    let zone = zones
+        .find_zone("_acme-challenge.www.sub.example.com")
+        .unwrap();
+    assert_eq!(zone.domain_name(), "sub.example.com");
+
+    let zone = zones.find_zone("_acme-challenge.www.example.com").unwrap();
+    assert_eq!(zone.domain_name(), "example.com");
+
+    assert!(zones.find_zone("_acme-challenge.example.org").is_none());
+    assert!(zones
+        .find_zone("_acme-challenge.www.notexample.com")
+        .is_none());
+}
+
+#[test]
+fn zone_contains_test() {
+    let zone = DnsZone::new("example.com.", "Z0001")
+        .with_delegations(["example.com.", "delegated.example.com."]);
+
+    assert!(zone.contains("example.com"));
+    assert!(zone.contains("_acme-challenge.www.example.com"));
+    assert!(zone.contains("_acme-challenge.WWW.Example.Com."));
+    assert!(!zone.contains("notexample.com"));
+    assert!(!zone.contains("_acme-challenge.www.notexample.com"));
+    assert!(!zone.contains("delegated.example.com"));
+    assert!(!zone.contains("_acme-challenge.www.delegated.example.com"));
+    assert!(zone.contains("_acme-challenge.notdelegated.example.com"));
+}
+
+/// Lists NS records only when asked, like Route53
+struct LazyNsZones(std::sync::Arc<std::sync::Mutex<Vec<String>>>);
+
+impl DnsProvider for LazyNsZones {
+    fn name(&self) -> &str {
+        "lazy"
+    }
+
+    fn list_zones(&self) -> DnsFuture<'_, Vec<DnsZone>> {
+        let zones = ["example.com", "example.net"]
+            .iter()
+            .map(|name| DnsZone::new(name, name))
+            .collect();
+        Box::pin(async move { Ok(zones) })
+    }
+
+    fn list_delegations<'a>(&'a self, zone: &'a DnsZone) -> Option<DnsFuture<'a, Vec<String>>> {
+        self.0.lock().unwrap().push(zone.domain_name().to_string());
+        Some(Box::pin(async {
+            Ok(vec![
+                "example.com.".to_string(),
+                "delegated.example.com.".to_string(),
+            ])
+        }))
+    }
+
+    fn upsert_txt<'a>(
+        &'a self,
+        _zone: &'a DnsZone,
+        _record_name: &'a str,
+        _txt_values: &'a [String],
+    ) -> DnsFuture<'a, DnsChangeInitialWait> {
+        Box::pin(async { Ok(DnsChangeInitialWait::ConstTime(0)) })
+    }
+
+    fn delete_txt<'a>(
+        &'a self,
+        _zone: &'a DnsZone,
+        _record_name: &'a str,
+        _txt_values: &'a [String],
+    ) -> DnsFuture<'a, ()> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+#[tokio::test]
+async fn load_delegations_test() {
+    let listed = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let zones = acme_client_route53::AllDnsZones::load_with_providers(vec![Box::new(LazyNsZones(
+        listed.clone(),
+    ))])
+    .await
+    .unwrap();
+    let record_name = "_acme-challenge.www.delegated.example.com";
+    assert!(zones.find_zone(record_name).is_some());
+
+    // Only the zone of the record, once
+    zones.load_delegations(record_name).await;
+    zones.load_delegations(record_name).await;
+    assert_eq!(*listed.lock().unwrap(), vec!["example.com"]);
+    assert!(zones.find_zone(record_name).is_none());
+    assert!(zones.find_zone("_acme-challenge.www.example.com").is_some());
+}
+
+#[tokio::test]
+async fn authoritative_check_test() {
+    use acme_client_route53::{DnsCheckConfig, DnsResponder, DnsResponderConfig};
+
+    // Two in-process servers act as authoritative name servers of the zone
+    let responder_config = DnsResponderConfig {
+        zone: "example.com".to_string(),
+        listen: vec!["127.0.0.1:0".parse().unwrap()],
+        nameserver: None,
+    };
+    let server1 = DnsResponder::bind(&responder_config).await.unwrap();
+    let server2 = DnsResponder::bind(&responder_config).await.unwrap();
+    let zones =
+        acme_client_route53::AllDnsZones::load_with_providers(vec![Box::new(StaticZones(vec![
+            "example.com",
+        ]))])
+        .await
+        .unwrap()
+        .with_dns_check(DnsCheckConfig {
+            authoritative_servers: [server1.local_addrs(), server2.local_addrs()].concat(),
+            ..Default::default()
+        });
+
+    let record_name = "_acme-challenge.www.example.com";
+    let txt_values = vec!["token-1".to_string()];
+    let zone = zones.find_zone(record_name).unwrap().clone();
+    let change = zones
+        .update_txt_record(record_name, &txt_values)
+        .await
+        .unwrap();
+
+    // Published on only one of them
+    server1
+        .upsert_txt(&zone, record_name, &txt_values)
+        .await
+        .unwrap();
+    assert!(change.wait_for_propergation(0).await.is_err());
+
+    server2
+        .upsert_txt(&zone, record_name, &txt_values)
+        .await
+        .unwrap();
+    assert!(change.wait_for_propergation(0).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_dns_answer_check_test() {
+    use acme_client_route53::DnsCheckConfig;
+
+    // Name servers are not queried
+    let zones =
+        acme_client_route53::AllDnsZones::load_with_providers(vec![Box::new(StaticZones(vec![
+            "example.com",
+        ]))])
+        .await
+        .unwrap()
+        .with_dns_check(DnsCheckConfig {
+            authoritative_servers: vec!["127.0.0.1:9".parse().unwrap()],
+            use_test_dns_answer: true,
+            ..Default::default()
+        });
+
+    let record_name = "_acme-challenge.www.example.com";
+    let txt_values = vec!["token-1".to_string()];
+    let change = zones
+        .update_txt_record(record_name, &txt_values)
+        .await
+        .unwrap();
+    assert!(change.wait_for_propergation(0).await.unwrap());
+
+    let txt_values = vec!["token-2".to_string()];
+    let change = zones
+        .update_txt_record(record_name, &txt_values)
+        .await
+        .unwrap();
+    assert!(change.wait_for_propergation(0).await.is_err());
+}
+
+#[tokio::test]
+async fn pinned_zone_test() {
+    use acme_client_route53::{DnsResponder, DnsResponderConfig, DnsZonePin};
+
+    // example.com is served by both providers
+    let responder = DnsResponder::bind(&DnsResponderConfig {
+        zone: "example.com".to_string(),
+        listen: vec!["127.0.0.1:0".parse().unwrap()],
+        nameserver: None,
+    })
+    .await
+    .unwrap();
+    let zones = acme_client_route53::AllDnsZones::load_with_providers(vec![
+        Box::new(StaticZones(vec!["example.com", "sub.example.com"])),
+        Box::new(responder),
+    ])
+    .await
+    .unwrap();
+
+    let record_name = "_acme-challenge.www.example.com";
+    let pin = DnsZonePin {
+        dns_provider: Some("STATIC".to_string()),
+        ..Default::default()
+    };
+    let zone = zones.find_pinned_zone(record_name, &pin).unwrap();
+    assert_eq!(zones.provider(zone).name(), "static");
+    let pin = DnsZonePin {
+        dns_provider: Some("responder".to_string()),
+        ..Default::default()
+    };
+    let zone = zones.find_pinned_zone(record_name, &pin).unwrap();
+    assert_eq!(zones.provider(zone).name(), "Responder");
+
+    // Route53 style id matches with or without "/hostedzone/"
+    let pin = DnsZonePin {
+        hosted_zone_id: Some("/hostedzone/sub.example.com".to_string()),
+        ..Default::default()
+    };
+    assert!(zones.find_pinned_zone(record_name, &pin).is_none());
+    let zone = zones
+        .find_pinned_zone("_acme-challenge.www.sub.example.com", &pin)
+        .unwrap();
+    assert_eq!(zone.zone_id(), "sub.example.com");
+}
@@ -0,0 +1,61 @@
+use acme_client_route53::{DnsProvider, DnsResponder, DnsResponderConfig};
+use hickory_proto::op::{Message, Query, ResponseCode};
+use hickory_proto::rr::{Name, RData, RecordType};
+use hickory_proto::serialize::binary::BinDecodable;
+
+async fn query(server: std::net::SocketAddr, name: &str, query_type: RecordType) -> Message {
+    let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let mut request = Message::new();
+    request.set_id(1234);
+    request.add_query(Query::query(Name::from_ascii(name).unwrap(), query_type));
+    socket
+        .send_to(&request.to_vec().unwrap(), server)
+        .await
+        .unwrap();
+
+    let mut buf = vec![0u8; 4096];
+    let len = socket.recv(&mut buf).await.unwrap();
+    Message::from_bytes(&buf[..len]).unwrap()
+}
+
+#[tokio::test]
+async fn dns_responder_test() {
+    let responder = DnsResponder::bind(&DnsResponderConfig {
+        zone: "acme.example.net".to_string(),
+        listen: vec!["127.0.0.1:0".parse().unwrap()],
+        nameserver: None,
+    })
+    .await
+    .unwrap();
+    let server = responder.local_addrs()[0];
+    let zones = responder.list_zones().await.unwrap();
+    let record_name = "www.acme.example.net";
+    let txt_values = vec!["token-1".to_string(), "token-2".to_string()];
+
+    responder
+        .upsert_txt(&zones[0], record_name, &txt_values)
+        .await
+        .unwrap();
+    let response = query(server, "WWW.acme.example.net.", RecordType::TXT).await;
+    assert_eq!(ResponseCode::NoError, response.response_code());
+    assert!(response.authoritative());
+    let answers = response
+        .answers()
+        .iter()
+        .filter_map(|record| match record.data() {
+            RData::TXT(txt) => Some(String::from_utf8_lossy(&txt.txt_data()[0]).to_string()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(txt_values, answers);
+
+    // Deleted, and names outside of the zone
+    responder
+        .delete_txt(&zones[0], record_name, &txt_values)
+        .await
+        .unwrap();
+    let response = query(server, "www.acme.example.net.", RecordType::TXT).await;
+    assert_eq!(ResponseCode::NXDomain, response.response_code());
+    let response = query(server, "www.example.com.", RecordType::TXT).await;
+    assert_eq!(ResponseCode::Refused, response.response_code());
+}
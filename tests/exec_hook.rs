@@ -0,0 +1,50 @@
+#![cfg(unix)]
+use acme_client_route53::{DnsProvider, ExecConfig, ExecProvider};
+
+fn exec_config(script: &str, out_file: &std::path::Path) -> ExecConfig {
+    ExecConfig {
+        command: "sh".to_string(),
+        // $0 is "hook", then present|cleanup, fqdn and value follow
+        args: vec!["-c".to_string(), script.to_string(), "hook".to_string()],
+        env: [("HOOK_OUT".to_string(), out_file.display().to_string())].into(),
+        zones: vec!["example.com".to_string()],
+        timeout_secs: 5,
+    }
+}
+
+#[tokio::test]
+async fn exec_hook_test() {
+    let out_file = std::env::temp_dir().join(format!("exec_hook_test_{}", std::process::id()));
+    let provider = ExecProvider::new(&exec_config(r#"echo "$1 $2 $3" >> "$HOOK_OUT""#, &out_file));
+    let zones = provider.list_zones().await.unwrap();
+    let txt_values = vec!["token-1".to_string(), "token-2".to_string()];
+
+    provider
+        .upsert_txt(&zones[0], "_acme-challenge.www.example.com", &txt_values)
+        .await
+        .unwrap();
+    provider
+        .delete_txt(
+            &zones[0],
+            "_acme-challenge.www.example.com",
+            &txt_values[..1],
+        )
+        .await
+        .unwrap();
+
+    let calls = std::fs::read_to_string(&out_file).unwrap();
+    std::fs::remove_file(&out_file).unwrap();
+    assert_eq!(
+        "present _acme-challenge.www.example.com. token-1\n\
+         present _acme-challenge.www.example.com. token-2\n\
+         cleanup _acme-challenge.www.example.com. token-1\n",
+        calls
+    );
+
+    // non-zero exit code
+    let provider = ExecProvider::new(&exec_config("exit 3", &out_file));
+    assert!(provider
+        .upsert_txt(&zones[0], "_acme-challenge.www.example.com", &txt_values)
+        .await
+        .is_err());
+}
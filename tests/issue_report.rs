@@ -0,0 +1,43 @@
+use acme_client_route53::{AuthorizationFailure, CertificateReport, IssueOutcome, IssueReport};
+
+#[test]
+fn authorization_failed_test() {
+    let e = acme_client_route53::Error::AuthorizationFailed(vec![
+        AuthorizationFailure {
+            hostname: "*.example.com".to_string(),
+            problem_type: Some("urn:ietf:params:acme:error:unauthorized".to_string()),
+            detail: "Incorrect TXT record \"old-token\" found at _acme-challenge.example.com"
+                .to_string(),
+            txt_value: Some("new-token".to_string()),
+        },
+        AuthorizationFailure {
+            hostname: "www.example.com".to_string(),
+            problem_type: None,
+            detail: "No DNS zone".to_string(),
+            txt_value: None,
+        },
+    ]);
+    assert_eq!(
+        "DNS01 challenge failed for *.example.com: Incorrect TXT record \"old-token\" found at \
+         _acme-challenge.example.com (unauthorized), expected TXT \"new-token\"; \
+         www.example.com: No DNS zone",
+        e.to_string()
+    );
+
+    // The report tells why for each certificate
+    let report = IssueReport {
+        certificates: vec![CertificateReport {
+            crt_file: "example.crt".to_string(),
+            outcome: IssueOutcome::Failed {
+                error: e.to_string(),
+            },
+            elapsed_secs: 1.0,
+            serial: None,
+            not_after: None,
+        }],
+        elapsed_secs: 1.0,
+    };
+    assert!(report
+        .to_string()
+        .starts_with("failed  example.crt: DNS01 challenge failed for *.example.com: Incorrect"));
+}
@@ -0,0 +1,115 @@
+use acme_client_route53::{DnsProvider, PowerDnsConfig, PowerDnsProvider};
+
+mod common;
+
+#[tokio::test]
+async fn powerdns_test() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let api_url = format!("http://{}", listener.local_addr().unwrap());
+    let server = tokio::spawn(common::serve_http(
+        listener,
+        vec![
+            r#"[{"id": "example.com.", "name": "example.com.", "kind": "Native"}]"#,
+            r#"{"id": "example.com.", "name": "example.com.", "rrsets": [
+                {"name": "example.com.", "type": "NS", "ttl": 3600, "records": [{"content": "ns1.example.com."}]},
+                {"name": "sub.example.com.", "type": "NS", "ttl": 3600, "records": [{"content": "ns1.example.net."}]}
+            ]}"#,
+            "",
+        ],
+    ));
+
+    let provider = PowerDnsProvider::new(&PowerDnsConfig {
+        api_url,
+        api_key: "secret-1".to_string(),
+        server_id: "localhost".to_string(),
+    })
+    .unwrap();
+
+    // zones with delegations
+    let zones = provider.list_zones().await.unwrap();
+    assert_eq!(1, zones.len());
+    assert_eq!("example.com", zones[0].domain_name());
+    assert!(zones[0].contains("_acme-challenge.www.example.com"));
+    assert!(!zones[0].contains("_acme-challenge.sub.example.com"));
+
+    let txt_values = vec!["token-1".to_string(), "token-2".to_string()];
+    provider
+        .upsert_txt(&zones[0], "_acme-challenge.www.example.com", &txt_values)
+        .await
+        .unwrap();
+
+    let requests = server.await.unwrap();
+    assert_eq!(
+        "GET /api/v1/servers/localhost/zones/",
+        requests[0].method_path
+    );
+    assert_eq!(
+        "GET /api/v1/servers/localhost/zones/example.com.",
+        requests[1].method_path
+    );
+    assert_eq!(
+        "PATCH /api/v1/servers/localhost/zones/example.com.",
+        requests[2].method_path
+    );
+    assert!(requests[2].head.contains("x-api-key: secret-1"));
+    let patch = serde_json::from_str::<serde_json::Value>(&requests[2].body).unwrap();
+    let rrset = &patch["rrsets"][0];
+    assert_eq!("_acme-challenge.www.example.com.", rrset["name"]);
+    assert_eq!("TXT", rrset["type"]);
+    assert_eq!("REPLACE", rrset["changetype"]);
+    assert_eq!("\"token-1\"", rrset["records"][0]["content"]);
+    assert_eq!("\"token-2\"", rrset["records"][1]["content"]);
+}
+
+#[tokio::test]
+async fn powerdns_delete_test() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let api_url = format!("http://{}", listener.local_addr().unwrap());
+    let server = tokio::spawn(common::serve_http(
+        listener,
+        vec![
+            r#"{"id": "example.com.", "name": "example.com.", "rrsets": [
+                {"name": "_acme-challenge.www.example.com.", "type": "TXT", "ttl": 60,
+                 "records": [{"content": "\"token-1\""}, {"content": "\"token-2\""}]}
+            ]}"#,
+            "",
+            r#"{"id": "example.com.", "name": "example.com.", "rrsets": [
+                {"name": "_acme-challenge.www.example.com.", "type": "TXT", "ttl": 60,
+                 "records": [{"content": "\"token-2\""}]}
+            ]}"#,
+            "",
+        ],
+    ));
+
+    let provider = PowerDnsProvider::new(&PowerDnsConfig {
+        api_url,
+        api_key: "secret-1".to_string(),
+        server_id: "localhost".to_string(),
+    })
+    .unwrap();
+    let zone = acme_client_route53::DnsZone::new("example.com.", "example.com.");
+    let record_name = "_acme-challenge.www.example.com";
+
+    // Another order still uses token-2
+    provider
+        .delete_txt(&zone, record_name, &["token-1".to_string()])
+        .await
+        .unwrap();
+    // The last value
+    provider
+        .delete_txt(&zone, record_name, &["token-2".to_string()])
+        .await
+        .unwrap();
+
+    let requests = server.await.unwrap();
+    assert!(requests[0]
+        .method_path
+        .starts_with("GET /api/v1/servers/localhost/zones/example.com.?"));
+    let patch = serde_json::from_str::<serde_json::Value>(&requests[1].body).unwrap();
+    let rrset = &patch["rrsets"][0];
+    assert_eq!("REPLACE", rrset["changetype"]);
+    assert_eq!(1, rrset["records"].as_array().unwrap().len());
+    assert_eq!("\"token-2\"", rrset["records"][0]["content"]);
+    let patch = serde_json::from_str::<serde_json::Value>(&requests[3].body).unwrap();
+    assert_eq!("DELETE", patch["rrsets"][0]["changetype"]);
+}
@@ -0,0 +1,95 @@
+use acme_client_route53::{DnsProvider, Rfc2136Config, Rfc2136Provider, TsigAlgorithm};
+use hickory_proto::dnssec::rdata::tsig::{
+    make_tsig_record, message_tbs, TsigAlgorithm as Alg, TSIG,
+};
+use hickory_proto::dnssec::tsig::TSigner;
+use hickory_proto::op::{Message, MessageType, OpCode, ResponseCode};
+use hickory_proto::rr::{DNSClass, Name, RData, RecordType};
+use hickory_proto::serialize::binary::{BinDecodable, BinEncodable};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+const TSIG_KEY_NAME: &str = "acme-key.";
+const TSIG_SECRET: &[u8] = b"0123456789abcdef0123456789abcdef";
+
+/// In-process DNS server, verifies one TSIG signed UPDATE and returns it
+async fn serve_one_update(listener: tokio::net::TcpListener) -> Message {
+    let key_name = Name::from_ascii(TSIG_KEY_NAME).unwrap();
+    let signer =
+        TSigner::new(TSIG_SECRET.to_vec(), Alg::HmacSha256, key_name.clone(), 300).unwrap();
+
+    let (mut stream, _) = listener.accept().await.unwrap();
+    let len = stream.read_u16().await.unwrap();
+    let mut request = vec![0u8; len as usize];
+    stream.read_exact(&mut request).await.unwrap();
+
+    let (request_mac, _, time) = signer.verify_message_byte(None, &request, true).unwrap();
+    let request = Message::from_bytes(&request).unwrap();
+
+    // Sign the response, chained to the request MAC
+    let mut response = Message::new();
+    response
+        .set_id(request.id())
+        .set_message_type(MessageType::Response)
+        .set_op_code(OpCode::Update)
+        .set_response_code(ResponseCode::NoError);
+    let pre_tsig = TSIG::new(
+        Alg::HmacSha256,
+        time,
+        300,
+        Vec::new(),
+        request.id(),
+        0,
+        Vec::new(),
+    );
+    let tbs = message_tbs(Some(&request_mac), &response, &pre_tsig, &key_name).unwrap();
+    let mac = signer.sign(&tbs).unwrap();
+    response.add_additional(make_tsig_record(key_name, pre_tsig.set_mac(mac)));
+
+    let response_bytes = response.to_bytes().unwrap();
+    stream.write_u16(response_bytes.len() as u16).await.unwrap();
+    stream.write_all(&response_bytes).await.unwrap();
+
+    request
+}
+
+#[tokio::test]
+async fn rfc2136_upsert_test() {
+    use base64::engine::{general_purpose::STANDARD, Engine};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let provider = Rfc2136Provider::new(&Rfc2136Config {
+        server: listener.local_addr().unwrap(),
+        zones: vec!["example.com".to_string()],
+        tsig_key_name: Some(TSIG_KEY_NAME.to_string()),
+        tsig_algorithm: TsigAlgorithm::HmacSha256,
+        tsig_secret: Some(STANDARD.encode(TSIG_SECRET)),
+    })
+    .unwrap();
+    let server = tokio::spawn(serve_one_update(listener));
+
+    let zones = provider.list_zones().await.unwrap();
+    let txt_values = vec!["token-1".to_string(), "token-2".to_string()];
+    provider
+        .upsert_txt(&zones[0], "_acme-challenge.www.example.com", &txt_values)
+        .await
+        .unwrap();
+
+    // Deletes the RRset, then adds each values
+    let request = server.await.unwrap();
+    assert_eq!(OpCode::Update, request.op_code());
+    assert_eq!(
+        Name::from_ascii("example.com.").unwrap(),
+        *request.queries()[0].name()
+    );
+    let updates = request.name_servers();
+    assert_eq!(3, updates.len());
+    assert_eq!(DNSClass::ANY, updates[0].dns_class());
+    assert_eq!(RecordType::TXT, updates[0].record_type());
+    for (update, txt_value) in updates[1..].iter().zip(&txt_values) {
+        assert_eq!(DNSClass::IN, update.dns_class());
+        let RData::TXT(txt) = update.data() else {
+            panic!("not TXT record");
+        };
+        assert_eq!(txt_value.as_bytes(), txt.txt_data()[0].as_ref());
+    }
+}
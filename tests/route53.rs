@@ -0,0 +1,144 @@
+use acme_client_route53::{DnsProvider, Route53Provider};
+
+mod common;
+
+const THROTTLING: &str = r#"<?xml version="1.0"?>
+<ErrorResponse xmlns="https://route53.amazonaws.com/doc/2013-04-01/"><Error><Type>Sender</Type><Code>Throttling</Code><Message>Rate exceeded</Message></Error><RequestId>r1</RequestId></ErrorResponse>"#;
+const ACCESS_DENIED: &str = r#"<?xml version="1.0"?>
+<ErrorResponse xmlns="https://route53.amazonaws.com/doc/2013-04-01/"><Error><Type>Sender</Type><Code>AccessDenied</Code><Message>Not authorized</Message></Error><RequestId>r2</RequestId></ErrorResponse>"#;
+const HOSTED_ZONES: &str = r#"<?xml version="1.0"?>
+<ListHostedZonesResponse xmlns="https://route53.amazonaws.com/doc/2013-04-01/"><HostedZones><HostedZone><Id>/hostedzone/Z0001</Id><Name>example.com.</Name><CallerReference>ref-1</CallerReference></HostedZone></HostedZones><Marker></Marker><IsTruncated>false</IsTruncated><MaxItems>100</MaxItems></ListHostedZonesResponse>"#;
+const NO_HOSTED_ZONES: &str = r#"<?xml version="1.0"?>
+<ListHostedZonesResponse xmlns="https://route53.amazonaws.com/doc/2013-04-01/"><HostedZones></HostedZones><Marker></Marker><IsTruncated>false</IsTruncated><MaxItems>100</MaxItems></ListHostedZonesResponse>"#;
+const CHANGE_PENDING: &str = r#"<?xml version="1.0"?>
+<ChangeResourceRecordSetsResponse xmlns="https://route53.amazonaws.com/doc/2013-04-01/"><ChangeInfo><Id>/change/C0001</Id><Status>PENDING</Status><SubmittedAt>2026-01-01T00:00:00Z</SubmittedAt></ChangeInfo></ChangeResourceRecordSetsResponse>"#;
+const CHANGE_INSYNC: &str = r#"<?xml version="1.0"?>
+<GetChangeResponse xmlns="https://route53.amazonaws.com/doc/2013-04-01/"><ChangeInfo><Id>/change/C0001</Id><Status>INSYNC</Status><SubmittedAt>2026-01-01T00:00:00Z</SubmittedAt></ChangeInfo></GetChangeResponse>"#;
+const INVALID_CHANGE_BATCH: &str = r#"<?xml version="1.0"?>
+<ErrorResponse xmlns="https://route53.amazonaws.com/doc/2013-04-01/"><Error><Type>Sender</Type><Code>InvalidChangeBatch</Code><Message>Tried to delete resource record set but it was not found</Message></Error><RequestId>r3</RequestId></ErrorResponse>"#;
+const NO_RECORDS: &str = r#"<?xml version="1.0"?>
+<ListResourceRecordSetsResponse xmlns="https://route53.amazonaws.com/doc/2013-04-01/"><ResourceRecordSets></ResourceRecordSets><IsTruncated>false</IsTruncated><MaxItems>100</MaxItems></ListResourceRecordSetsResponse>"#;
+
+fn route53_client(endpoint_url: String) -> aws_sdk_route53::Client {
+    use aws_sdk_route53::config::{retry::RetryConfig, BehaviorVersion, Credentials, Region};
+
+    let config = aws_sdk_route53::Config::builder()
+        .behavior_version(BehaviorVersion::latest())
+        .region(Region::new("us-east-1"))
+        .credentials_provider(Credentials::new("AKID", "SECRET", None, None, "test"))
+        .endpoint_url(endpoint_url)
+        .retry_config(RetryConfig::disabled())
+        .build();
+    aws_sdk_route53::Client::from_conf(config)
+}
+
+#[tokio::test]
+async fn route53_retry_test() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let endpoint_url = format!("http://{}", listener.local_addr().unwrap());
+    let server = tokio::spawn(common::serve_http_with_status(
+        listener,
+        vec![
+            (400, THROTTLING),
+            (200, HOSTED_ZONES),
+            (200, NO_RECORDS),
+            (403, ACCESS_DENIED),
+            (200, NO_HOSTED_ZONES),
+        ],
+    ));
+    let provider = Route53Provider::new(route53_client(endpoint_url));
+
+    // Throttled request is retried
+    let zones = provider.list_zones().await.unwrap();
+    assert_eq!(1, zones.len());
+    assert_eq!("example.com", zones[0].domain_name());
+
+    // NS records are listed only on demand
+    let delegations = provider.list_delegations(&zones[0]).unwrap().await;
+    assert!(delegations.unwrap().is_empty());
+
+    // AccessDenied is not
+    assert!(provider.list_zones().await.is_err());
+    assert!(provider.list_zones().await.unwrap().is_empty());
+
+    let requests = server.await.unwrap();
+    assert_eq!(5, requests.len());
+    assert_eq!(requests[0].method_path, requests[1].method_path);
+}
+
+fn change_id(wait: acme_client_route53::DnsChangeInitialWait) -> String {
+    match wait {
+        acme_client_route53::DnsChangeInitialWait::ChangeId(change_id) => change_id,
+        _ => panic!("no change id"),
+    }
+}
+
+#[tokio::test]
+async fn route53_batch_test() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let endpoint_url = format!("http://{}", listener.local_addr().unwrap());
+    let server = tokio::spawn(common::serve_http(
+        listener,
+        vec![CHANGE_PENDING, CHANGE_INSYNC],
+    ));
+    let provider = Route53Provider::new(route53_client(endpoint_url));
+    let zone = acme_client_route53::DnsZone::new("example.com.", "/hostedzone/Z0001");
+    let txt_values = vec!["token-1".to_string()];
+
+    // Upserts in the same zone share one ChangeBatch and one GetChange poller
+    let (www, api) = futures::future::join(
+        provider.upsert_txt(&zone, "_acme-challenge.www.example.com", &txt_values),
+        provider.upsert_txt(&zone, "_acme-challenge.api.example.com", &txt_values),
+    )
+    .await;
+    let (www, api) = (change_id(www.unwrap()), change_id(api.unwrap()));
+    assert_eq!("/change/C0001", www);
+    assert_eq!(www, api);
+    let timeout = std::time::Duration::from_secs(5);
+    let (www, api) = futures::future::join(
+        provider.wait_for_change(&www, timeout),
+        provider.wait_for_change(&api, timeout),
+    )
+    .await;
+    www.unwrap();
+    api.unwrap();
+
+    let requests = server.await.unwrap();
+    assert_eq!(2, requests.len());
+    assert!(requests[0].body.contains("_acme-challenge.www.example.com"));
+    assert!(requests[0].body.contains("_acme-challenge.api.example.com"));
+    assert!(requests[1].method_path.starts_with("GET "));
+}
+
+#[tokio::test]
+async fn route53_invalid_batch_test() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let endpoint_url = format!("http://{}", listener.local_addr().unwrap());
+    let server = tokio::spawn(common::serve_http_with_status(
+        listener,
+        vec![
+            (400, INVALID_CHANGE_BATCH),
+            (200, CHANGE_PENDING),
+            (400, INVALID_CHANGE_BATCH),
+        ],
+    ));
+    let provider = Route53Provider::new(route53_client(endpoint_url));
+    let zone = acme_client_route53::DnsZone::new("example.com.", "/hostedzone/Z0001");
+    let txt_values = vec!["token-1".to_string()];
+
+    // Rejected batch is sent again change by change, only the bad one fails
+    let (www, api) = futures::future::join(
+        provider.delete_txt(&zone, "_acme-challenge.www.example.com", &txt_values),
+        provider.delete_txt(&zone, "_acme-challenge.api.example.com", &txt_values),
+    )
+    .await;
+    assert!(www.is_ok());
+    assert!(api.is_err());
+
+    let requests = server.await.unwrap();
+    assert_eq!(3, requests.len());
+    assert!(requests[0].body.contains("_acme-challenge.api.example.com"));
+    assert!(requests[1].body.contains("_acme-challenge.www.example.com"));
+    assert!(!requests[1].body.contains("_acme-challenge.api.example.com"));
+    assert!(requests[2].body.contains("_acme-challenge.api.example.com"));
+}